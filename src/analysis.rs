@@ -0,0 +1,199 @@
+use std::f64;
+use std::fmt;
+use std::io::{Error, ErrorKind, Result};
+
+use image::{Image, Pixel};
+use units::{DistPx, PX};
+
+/// Quantitative measures of the difference between two images
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ComparisonStats {
+    /// The root-mean-square difference between corresponding pixels
+    pub rmse: f64,
+
+    /// The peak signal-to-noise ratio in dB, relative to the peak value of the
+    /// pixel type. Infinite if the images are identical.
+    pub psnr: f64,
+
+    /// The largest absolute difference between any two corresponding pixels
+    pub max_abs_diff: f64,
+
+    /// The coordinates of the (first) pixel with the largest difference
+    pub max_diff_at: (DistPx, DistPx),
+}
+
+impl fmt::Display for ComparisonStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (x, y) = self.max_diff_at;
+        writeln!(f, "RMSE: {}", self.rmse)?;
+        writeln!(f, "PSNR: {} dB", self.psnr)?;
+        write!(f,
+               "Max absolute difference: {} at ({}, {})",
+               self.max_abs_diff,
+               x / PX,
+               y / PX)
+    }
+}
+
+/// The absolute difference between two pixel values, where a NaN differs
+/// infinitely from anything but another NaN.
+fn abs_diff(a: f64, b: f64) -> f64 {
+    if a == b || (a.is_nan() && b.is_nan()) {
+        0.0
+    } else if a.is_nan() || b.is_nan() {
+        f64::INFINITY
+    } else {
+        (a - b).abs()
+    }
+}
+
+/// Compares two images of the same size pixel-by-pixel. All of the
+/// arithmetic is done in `f64`, so the results are exact for integral pixel
+/// types. A NaN pixel is treated as infinitely different from any other value
+/// except another NaN.
+pub fn compare<PixelType, I1, I2>(a: &I1, b: &I2) -> Result<ComparisonStats>
+    where PixelType: Pixel,
+          I1: Image<PixelType>,
+          I2: Image<PixelType>
+{
+    let (width, height) = a.dimensions();
+    if b.dimensions() != (width, height) {
+        return Err(Error::new(ErrorKind::InvalidInput,
+                              "Image dimensions differ"));
+    }
+
+    let count = a.pixels().len();
+    if count == 0 {
+        return Ok(ComparisonStats {
+                      rmse: 0.0,
+                      psnr: f64::INFINITY,
+                      max_abs_diff: 0.0,
+                      max_diff_at: (0isize * PX, 0isize * PX),
+                  });
+    }
+
+    let w = width / PX;
+    let mut sum_sq = 0.0;
+    let mut max_abs_diff = 0.0;
+    let mut max_offset = 0;
+    for (n, (pa, pb)) in a.pixels().iter().zip(b.pixels().iter()).enumerate() {
+        let d = abs_diff(pa.to_f64().unwrap(), pb.to_f64().unwrap());
+        sum_sq += d * d;
+        if d > max_abs_diff {
+            max_abs_diff = d;
+            max_offset = n as isize;
+        }
+    }
+
+    let mse = sum_sq / count as f64;
+    let psnr = if mse == 0.0 {
+        f64::INFINITY
+    } else {
+        10.0 * ((PixelType::peak_value() * PixelType::peak_value()) / mse)
+                   .log10()
+    };
+
+    Ok(ComparisonStats {
+           rmse: mse.sqrt(),
+           psnr: psnr,
+           max_abs_diff: max_abs_diff,
+           max_diff_at: ((max_offset % w) * PX, (max_offset / w) * PX),
+       })
+}
+
+#[cfg(test)]
+mod test_compare {
+    use super::compare;
+    use image::{MutableImage, OwnedImage};
+    use std::f32;
+    use units::PX;
+
+    fn assert_close(a: f64, b: f64) {
+        assert!((a - b).abs() < 1e-6, "{} != {}", a, b);
+    }
+
+    #[test]
+    fn identical_images() {
+        let a = OwnedImage::<i16>::checkerboard(8isize * PX,
+                                                4isize * PX,
+                                                2isize * PX,
+                                                0,
+                                                1000);
+        let stats = compare(&a, &a).unwrap();
+
+        assert_eq!(stats.rmse, 0.0);
+        assert!(stats.psnr.is_infinite() && stats.psnr > 0.0);
+        assert_eq!(stats.max_abs_diff, 0.0);
+    }
+
+    #[test]
+    fn single_pixel_difference() {
+        // One pixel out of 16 differs by 100, so:
+        //   MSE  = 100^2 / 16 = 625
+        //   RMSE = 25
+        //   PSNR = 20 * log10(32767 / 25) ~= 62.3499 dB
+        let a = OwnedImage::<i16>::new(4isize * PX, 4isize * PX);
+        let mut b = OwnedImage::<i16>::new(4isize * PX, 4isize * PX);
+        b[(2isize * PX, 1isize * PX)] = 100;
+
+        let stats = compare(&a, &b).unwrap();
+        assert_close(stats.rmse, 25.0);
+        assert_close(stats.psnr, 62.349933449);
+        assert_close(stats.max_abs_diff, 100.0);
+        assert_eq!(stats.max_diff_at, (2isize * PX, 1isize * PX));
+
+        // the comparison is symmetric
+        assert_eq!(compare(&b, &a).unwrap(), stats);
+    }
+
+    #[test]
+    fn float_psnr_is_relative_to_one() {
+        // One pixel out of 4 differs by 0.5, so:
+        //   MSE  = 0.25 / 4 = 0.0625
+        //   PSNR = 10 * log10(1 / 0.0625) ~= 12.0412 dB
+        let mut a = OwnedImage::<f32>::new(2isize * PX, 2isize * PX);
+        a.fill(0.25);
+        let mut b = OwnedImage::<f32>::new(2isize * PX, 2isize * PX);
+        b.fill(0.25);
+        b[(1isize * PX, 1isize * PX)] = 0.75;
+
+        let stats = compare(&a, &b).unwrap();
+        assert_close(stats.rmse, 0.25);
+        assert_close(stats.psnr, 12.041199827);
+        assert_close(stats.max_abs_diff, 0.5);
+        assert_eq!(stats.max_diff_at, (1isize * PX, 1isize * PX));
+    }
+
+    #[test]
+    fn nan_differs_infinitely() {
+        let a = OwnedImage::<f32>::new(2isize * PX, 2isize * PX);
+        let mut b = OwnedImage::<f32>::new(2isize * PX, 2isize * PX);
+        b[(1isize * PX, 0isize * PX)] = f32::NAN;
+
+        let stats = compare(&a, &b).unwrap();
+        assert!(stats.max_abs_diff.is_infinite());
+        assert_eq!(stats.max_diff_at, (1isize * PX, 0isize * PX));
+        assert!(stats.rmse.is_infinite());
+        assert!(stats.psnr.is_infinite() && stats.psnr < 0.0);
+
+        // ...unless both pixels are NaN
+        let stats = compare(&b, &b).unwrap();
+        assert_eq!(stats.max_abs_diff, 0.0);
+        assert_eq!(stats.rmse, 0.0);
+    }
+
+    #[test]
+    fn empty_images() {
+        let a = OwnedImage::<i16>::new(0isize * PX, 4isize * PX);
+        let stats = compare(&a, &a).unwrap();
+        assert_eq!(stats.rmse, 0.0);
+        assert_eq!(stats.max_abs_diff, 0.0);
+    }
+
+    #[test]
+    fn mismatched_dimensions_are_an_error() {
+        let a = OwnedImage::<i16>::new(4isize * PX, 4isize * PX);
+        let b = OwnedImage::<i16>::new(4isize * PX, 5isize * PX);
+        assert!(compare(&a, &b).is_err());
+    }
+}
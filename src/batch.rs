@@ -0,0 +1,273 @@
+use std::collections::HashSet;
+use std::fmt;
+use std::fs;
+use std::io::{Error, ErrorKind, Result};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// The outcome of processing a batch of files
+#[derive(Debug)]
+pub struct Summary {
+    pub succeeded: usize,
+    pub failed: usize,
+    pub elapsed: Duration,
+}
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let secs = self.elapsed.as_secs() as f64 +
+                   (self.elapsed.subsec_nanos() as f64 * 1e-9);
+        write!(f,
+               "Processed {} files: {} succeeded, {} failed in {:.3}s",
+               self.succeeded + self.failed,
+               self.succeeded,
+               self.failed,
+               secs)
+    }
+}
+
+/// Expands an input path into the list of files to process. A directory is
+/// expanded into the regular files it contains (but not those in any
+/// sub-directories), in name order. Anything else is taken as-is.
+fn expand_input(path: &Path) -> Result<Vec<PathBuf>> {
+    if !path.is_dir() {
+        return Ok(vec![path.to_path_buf()]);
+    }
+
+    let mut files = Vec::new();
+    for entry in fs::read_dir(path)? {
+        let p = entry?.path();
+        if p.is_file() {
+            files.push(p);
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Works out where the result of processing an input file should be written,
+/// refusing to overwrite the input itself.
+fn output_path(input: &Path, output_dir: &Path) -> Result<PathBuf> {
+    let name = input.file_name()
+        .ok_or_else(|| Error::new(ErrorKind::InvalidInput, "Not a file"))?;
+    let dst = output_dir.join(name);
+
+    if dst.exists() && fs::canonicalize(&dst)? == fs::canonicalize(input)? {
+        return Err(Error::new(ErrorKind::InvalidInput,
+                              "Output would overwrite input"));
+    }
+
+    Ok(dst)
+}
+
+/// Applies `f` to every input file, giving it the path of the input and the
+/// path the result should be written to (if any). A failure on one file is
+/// logged and counted, but does not stop the rest of the batch.
+///
+/// Inputs from different directories may share a name. Only the first of
+/// them is processed; the rest are failed rather than allowed to overwrite
+/// its result.
+pub fn run<F>(inputs: &[PathBuf],
+              output_dir: Option<&Path>,
+              mut f: F)
+              -> Summary
+    where F: FnMut(&Path, Option<&Path>) -> Result<()>
+{
+    let start = Instant::now();
+    let mut summary = Summary {
+        succeeded: 0,
+        failed: 0,
+        elapsed: Duration::from_secs(0),
+    };
+
+    let mut claimed = HashSet::new();
+    for input in inputs {
+        let files = match expand_input(input) {
+            Ok(files) => files,
+            Err(e) => {
+                error!("{:?}: {}", input, e);
+                summary.failed += 1;
+                continue;
+            }
+        };
+
+        for file in files {
+            debug!("Processing {:?}", file);
+            let result = match output_dir {
+                Some(dir) => {
+                    output_path(&file, dir).and_then(|dst| {
+                        if !claimed.insert(dst.clone()) {
+                            let msg = format!("Output {:?} was already \
+                                               written by an earlier input",
+                                              dst);
+                            return Err(Error::new(ErrorKind::AlreadyExists,
+                                                  msg));
+                        }
+                        f(&file, Some(dst.as_path()))
+                    })
+                }
+                None => f(&file, None),
+            };
+
+            match result {
+                Ok(()) => summary.succeeded += 1,
+                Err(e) => {
+                    error!("{:?}: {}", file, e);
+                    summary.failed += 1;
+                }
+            }
+        }
+    }
+
+    summary.elapsed = start.elapsed();
+    summary
+}
+
+#[cfg(test)]
+pub mod test_util {
+    use std::fs;
+    use std::path::{Path, PathBuf};
+    use tempfile::NamedTempFile;
+
+    /// A uniquely-named scratch directory that is deleted when dropped. The
+    /// name is reserved by creating a temp file alongside it.
+    pub struct TempDir {
+        _name: NamedTempFile,
+        path: PathBuf,
+    }
+
+    impl TempDir {
+        pub fn new() -> ::std::io::Result<TempDir> {
+            let name = NamedTempFile::new()?;
+            let path = name.path().with_extension("d");
+            fs::create_dir(&path)?;
+            Ok(TempDir {
+                   _name: name,
+                   path: path,
+               })
+        }
+
+        pub fn path(&self) -> &Path {
+            self.path.as_path()
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.path);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_batch {
+    use super::*;
+    use super::test_util::TempDir;
+    use std::fs::File;
+    use std::io::{Error, ErrorKind};
+
+    fn touch(dir: &Path, name: &str) -> PathBuf {
+        let p = dir.join(name);
+        File::create(&p).unwrap();
+        p
+    }
+
+    #[test]
+    fn directories_are_expanded_in_name_order() {
+        let dir = TempDir::new().unwrap();
+        touch(dir.path(), "b.raw");
+        touch(dir.path(), "a.raw");
+        fs::create_dir(dir.path().join("sub")).unwrap();
+        touch(&dir.path().join("sub"), "c.raw");
+
+        let files = expand_input(dir.path()).unwrap();
+        assert_eq!(files,
+                   vec![dir.path().join("a.raw"), dir.path().join("b.raw")]);
+    }
+
+    #[test]
+    fn output_is_named_after_input() {
+        let src = TempDir::new().unwrap();
+        let dst = TempDir::new().unwrap();
+        let input = touch(src.path(), "frame_0001.raw");
+
+        assert_eq!(output_path(&input, dst.path()).unwrap(),
+                   dst.path().join("frame_0001.raw"));
+    }
+
+    #[test]
+    fn output_may_not_overwrite_input() {
+        let src = TempDir::new().unwrap();
+        let input = touch(src.path(), "frame_0001.raw");
+
+        assert!(output_path(&input, src.path()).is_err());
+    }
+
+    #[test]
+    fn failures_do_not_stop_the_batch() {
+        let src = TempDir::new().unwrap();
+        let dst = TempDir::new().unwrap();
+        touch(src.path(), "1.raw");
+        touch(src.path(), "2.raw");
+        touch(src.path(), "3.raw");
+
+        let mut seen = Vec::new();
+        let summary = run(&[src.path().to_path_buf()],
+                          Some(dst.path()),
+                          |input, output| {
+            seen.push(output.unwrap().to_path_buf());
+            if input.ends_with("2.raw") {
+                Err(Error::new(ErrorKind::Other, "boom"))
+            } else {
+                Ok(())
+            }
+        });
+
+        assert_eq!(summary.succeeded, 2);
+        assert_eq!(summary.failed, 1);
+        assert_eq!(seen,
+                   vec![dst.path().join("1.raw"),
+                        dst.path().join("2.raw"),
+                        dst.path().join("3.raw")]);
+    }
+
+    #[test]
+    fn clashing_output_names_are_failures() {
+        let a = TempDir::new().unwrap();
+        let b = TempDir::new().unwrap();
+        let dst = TempDir::new().unwrap();
+        touch(a.path(), "frame_0001.raw");
+        touch(a.path(), "frame_0002.raw");
+        touch(b.path(), "frame_0001.raw");
+
+        let mut seen = Vec::new();
+        let summary = run(&[a.path().to_path_buf(), b.path().to_path_buf()],
+                          Some(dst.path()),
+                          |input, _| {
+                              seen.push(input.to_path_buf());
+                              Ok(())
+                          });
+
+        assert_eq!(summary.succeeded, 2);
+        assert_eq!(summary.failed, 1);
+        assert_eq!(seen,
+                   vec![a.path().join("frame_0001.raw"),
+                        a.path().join("frame_0002.raw")]);
+    }
+
+    #[test]
+    fn missing_inputs_are_failures() {
+        let dir = TempDir::new().unwrap();
+        let inputs = vec![touch(dir.path(), "1.raw"),
+                          dir.path().join("nonesuch.raw")];
+
+        let summary = run(&inputs, None, |input, _| if input.exists() {
+            Ok(())
+        } else {
+            Err(Error::new(ErrorKind::NotFound, "missing"))
+        });
+
+        assert_eq!(summary.succeeded, 1);
+        assert_eq!(summary.failed, 1);
+    }
+}
@@ -1,8 +1,14 @@
 use std::env;
+use std::ffi::OsString;
 use std::io;
 use std::path::{Path, PathBuf};
-use clap::{App, Arg};
+use clap::{self, App, Arg, ErrorKind};
 
+use convert::ConvertMode;
+use distort::Interpolation;
+use image::PixelFormat;
+use patterns::Axis;
+use transform::Orientation;
 use units::{DistPx, DistPxFrac, PX};
 
 /// Attempts to expand a relative filename into a fully-qualified path.
@@ -22,16 +28,263 @@ mod test_expand_filename {
     }
 }
 
+/// A synthetic test pattern, as described on the command line. Pixel values
+/// are held as `f64` and converted to the output pixel type when the pattern
+/// is rendered.
+#[derive(Clone)]
+pub enum Pattern {
+    Checkerboard {
+        cell: DistPx,
+        low: f64,
+        high: f64,
+    },
+    Gradient(Axis),
+    Grid {
+        spacing: DistPx,
+        line: f64,
+        background: f64,
+    },
+}
+
+/// Where to read an input image from
+#[derive(Clone, Debug, PartialEq)]
+pub enum Input {
+    Stdin,
+    File(PathBuf),
+}
+
+/// Where to write an output image to
+#[derive(Clone, Debug, PartialEq)]
+pub enum Output {
+    Stdout,
+    File(PathBuf),
+}
+
+/// The filename used on the command line to mean stdin or stdout
+const STD_STREAM: &str = "-";
+
+/// What the user has asked firkin to do
+#[derive(Clone)]
+pub enum Action {
+    /// Correct the given image
+    Correct(Input),
+
+    /// Correct every image in the given files and/or directories
+    Batch(Vec<PathBuf>),
+
+    /// Generate a test pattern rather than reading an input image
+    Generate(Pattern),
+}
+
+/// A rectangular region of an image
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Region {
+    pub origin: (DistPx, DistPx),
+    pub size: (DistPx, DistPx),
+}
+
+#[derive(Clone)]
 pub struct Options {
-    pub input: PathBuf,
+    pub action: Action,
+    pub output: Option<Output>,
+
+    /// Where to write the results of a batch. Each output file has the same
+    /// name as its input file.
+    pub output_dir: Option<PathBuf>,
     pub width: DistPx,
     pub height: DistPx,
+    pub format: PixelFormat,
+
+    /// The region of the input image to keep. Cropping happens before any
+    /// other processing, so every later stage (including correction, and the
+    /// optical centre it uses) sees only the cropped image.
+    pub crop: Option<Region>,
+
+    /// Rotation or mirroring applied to the corrected image
+    pub orient: Option<Orientation>,
+
+    /// The final size of the output image. Resizing happens after any
+    /// reorientation, so this is always the size of the written image.
+    pub resize: Option<(DistPx, DistPx)>,
+
+    /// How new pixel values are synthesized when the image is resampled
+    pub interpolation: Interpolation,
+
+    /// The number of sub-samples taken along each axis of a destination
+    /// pixel whenever the image is resampled. One means no supersampling.
+    pub supersample: usize,
+
+    /// A reference image to compare the processed image against
+    pub compare: Option<PathBuf>,
+
+    /// The largest per-pixel difference from the reference image that still
+    /// counts as a match
+    pub tolerance: f64,
+
+    /// The pixel type to write the output in, if it differs from the input
+    pub output_format: Option<PixelFormat>,
+
+    /// How pixel values are converted to the output pixel type
+    pub convert_mode: ConvertMode,
+
+    /// Treat unexpected trailing data on the input stream as an error rather
+    /// than a warning
+    pub strict: bool,
 }
 
 mod arg {
     pub const IMAGE: &str = "image";
+    pub const OUTPUT: &str = "output";
+    pub const OUTPUT_DIR: &str = "output-dir";
     pub const WIDTH: &str = "width";
     pub const HEIGHT: &str = "height";
+    pub const FORMAT: &str = "format";
+    pub const OUTPUT_FORMAT: &str = "output-format";
+    pub const CONVERT_MODE: &str = "convert-mode";
+    pub const GENERATE: &str = "generate";
+    pub const CELL: &str = "cell";
+    pub const AXIS: &str = "axis";
+    pub const LOW: &str = "low";
+    pub const HIGH: &str = "high";
+    pub const CROP: &str = "crop";
+    pub const ORIENT: &str = "orient";
+    pub const RESIZE: &str = "resize";
+    pub const INTERPOLATION: &str = "interpolation";
+    pub const STRICT: &str = "strict";
+    pub const SUPERSAMPLE: &str = "supersample";
+    pub const COMPARE: &str = "compare";
+    pub const TOLERANCE: &str = "tolerance";
+}
+
+mod format {
+    pub const I16: &str = "i16";
+    pub const I32: &str = "i32";
+    pub const F32: &str = "f32";
+}
+
+mod convert_mode {
+    pub const CAST: &str = "cast";
+    pub const SATURATE: &str = "saturate";
+    pub const RESCALE: &str = "rescale";
+}
+
+mod interpolation {
+    pub const NEAREST: &str = "nearest";
+    pub const BILINEAR: &str = "bilinear";
+}
+
+mod orientation {
+    pub const FLIP_H: &str = "fliph";
+    pub const FLIP_V: &str = "flipv";
+    pub const ROT_90: &str = "rot90";
+    pub const ROT_180: &str = "rot180";
+    pub const ROT_270: &str = "rot270";
+}
+
+mod pattern {
+    pub const CHECKERBOARD: &str = "checkerboard";
+    pub const GRADIENT: &str = "gradient";
+    pub const GRID: &str = "grid";
+}
+
+fn is_positive_int(s: String) -> Result<(), String> {
+    match s.parse::<isize>() {
+        Ok(n) if n > 0 => Ok(()),
+        _ => Err(format!("expected a positive integer, got \"{}\"", s)),
+    }
+}
+
+/// Parses a pair of image dimensions in the form `WxH`.
+fn parse_dimensions(s: &str) -> Result<(DistPx, DistPx), String> {
+    let err = || format!("expected dimensions as WIDTHxHEIGHT, got \"{}\"", s);
+
+    let mut parts = s.splitn(2, 'x');
+    let mut next = || {
+        parts.next()
+            .and_then(|p| p.trim().parse::<isize>().ok())
+            .and_then(|n| if n > 0 { Some(n * PX) } else { None })
+            .ok_or_else(&err)
+    };
+    let width = next()?;
+    let height = next()?;
+    Ok((width, height))
+}
+
+#[cfg(test)]
+mod test_parse_dimensions {
+    use super::parse_dimensions;
+    use units::PX;
+
+    #[test]
+    fn valid_dimensions() {
+        let (w, h) = parse_dimensions("640x480").unwrap();
+        assert_eq!(w, 640isize * PX);
+        assert_eq!(h, 480isize * PX);
+    }
+
+    #[test]
+    fn malformed_dimensions_are_an_error() {
+        for s in &["", "640", "640x", "x480", "640x480x2", "0x480", "-1x480",
+                   "axb", "640,480"] {
+            assert!(parse_dimensions(s).is_err(), "{} should not parse", s);
+        }
+    }
+}
+
+/// Parses a crop region in the form `X,Y,W,H`.
+fn parse_region(s: &str) -> Result<Region, String> {
+    let err = || format!("expected a region as X,Y,WIDTH,HEIGHT, got \"{}\"", s);
+
+    let values = s.split(',')
+        .map(|p| p.trim().parse::<isize>())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|_| err())?;
+    if values.len() != 4 {
+        return Err(err());
+    }
+
+    let (x, y, w, h) = (values[0], values[1], values[2], values[3]);
+    if x < 0 || y < 0 {
+        return Err(format!("crop origin must not be negative, got \"{}\"", s));
+    }
+    if w <= 0 || h <= 0 {
+        return Err(format!("crop region must not be empty, got \"{}\"", s));
+    }
+
+    Ok(Region {
+           origin: (x * PX, y * PX),
+           size: (w * PX, h * PX),
+       })
+}
+
+#[cfg(test)]
+mod test_parse_region {
+    use super::{parse_region, Region};
+    use units::PX;
+
+    #[test]
+    fn valid_region() {
+        let r = parse_region("10, 20,300,400").unwrap();
+        assert_eq!(r,
+                   Region {
+                       origin: (10isize * PX, 20isize * PX),
+                       size: (300isize * PX, 400isize * PX),
+                   });
+    }
+
+    #[test]
+    fn malformed_regions_are_an_error() {
+        for s in &["", "1,2,3", "1,2,3,4,5", "a,b,c,d", "1,2,3,", "1;2;3;4",
+                   "1.5,2,3,4", "-1,0,10,10"] {
+            assert!(parse_region(s).is_err(), "{} should not parse", s);
+        }
+    }
+
+    #[test]
+    fn zero_area_regions_are_an_error() {
+        assert!(parse_region("0,0,0,10").is_err());
+        assert!(parse_region("0,0,10,0").is_err());
+    }
 }
 
 fn build_cmd_line<'a, 'b>() -> App<'a, 'b> {
@@ -40,10 +293,26 @@ fn build_cmd_line<'a, 'b>() -> App<'a, 'b> {
         .arg(Arg::with_name(arg::IMAGE)
                  .long("image")
                  .short("i")
-                 .help("The input file")
+                 .help("The input file. Reads from stdin if omitted or \
+                        \"-\". May be given more than once, or name a \
+                        directory, to process a batch of files")
+                 .value_name("FILE")
+                 .takes_value(true)
+                 .multiple(true)
+                 .number_of_values(1))
+        .arg(Arg::with_name(arg::OUTPUT)
+                 .long("output")
+                 .short("o")
+                 .help("The output file. Use \"-\" to write to stdout")
                  .value_name("FILE")
+                 .takes_value(true))
+        .arg(Arg::with_name(arg::OUTPUT_DIR)
+                 .long("output-dir")
+                 .help("The directory to write batch results to. Required \
+                        when processing a batch")
+                 .value_name("DIR")
                  .takes_value(true)
-                 .required(true))
+                 .conflicts_with(arg::OUTPUT))
         .arg(Arg::with_name(arg::WIDTH)
                  .long("width")
                  .short("w")
@@ -58,21 +327,554 @@ fn build_cmd_line<'a, 'b>() -> App<'a, 'b> {
                  .takes_value(true)
                  .value_name("INT")
                  .default_value("800"))
+        .arg(Arg::with_name(arg::FORMAT)
+                 .long("format")
+                 .short("f")
+                 .help("The pixel type of the image")
+                 .takes_value(true)
+                 .value_name("FORMAT")
+                 .possible_values(&[format::I16, format::I32, format::F32])
+                 .default_value(format::I16))
+        .arg(Arg::with_name(arg::OUTPUT_FORMAT)
+                 .long("output-format")
+                 .help("The pixel type to write the output image in. \
+                        Defaults to the input pixel type")
+                 .takes_value(true)
+                 .value_name("FORMAT")
+                 .possible_values(&[format::I16, format::I32, format::F32]))
+        .arg(Arg::with_name(arg::CONVERT_MODE)
+                 .long("convert-mode")
+                 .help("How to map pixel values into the output pixel type. \
+                        Requires --output-format")
+                 .takes_value(true)
+                 .value_name("MODE")
+                 .possible_values(&[convert_mode::CAST,
+                                    convert_mode::SATURATE,
+                                    convert_mode::RESCALE])
+                 .default_value(convert_mode::SATURATE))
+        .arg(Arg::with_name(arg::GENERATE)
+                 .long("generate")
+                 .help("Write a synthetic test pattern instead of correcting \
+                        an image")
+                 .takes_value(true)
+                 .value_name("PATTERN")
+                 .possible_values(&[pattern::CHECKERBOARD,
+                                    pattern::GRADIENT,
+                                    pattern::GRID])
+                 .conflicts_with(arg::IMAGE)
+                 .requires(arg::OUTPUT))
+        .arg(Arg::with_name(arg::CELL)
+                 .long("cell")
+                 .help("Size of a checkerboard cell, or the spacing between \
+                        grid lines")
+                 .takes_value(true)
+                 .value_name("INT")
+                 .validator(is_positive_int)
+                 .default_value("32"))
+        .arg(Arg::with_name(arg::AXIS)
+                 .long("axis")
+                 .help("Direction in which a gradient increases")
+                 .takes_value(true)
+                 .value_name("AXIS")
+                 .possible_values(&["x", "y"])
+                 .default_value("x"))
+        .arg(Arg::with_name(arg::LOW)
+                 .long("low")
+                 .help("Background pixel value for generated patterns")
+                 .takes_value(true)
+                 .value_name("NUM")
+                 .default_value("0"))
+        .arg(Arg::with_name(arg::HIGH)
+                 .long("high")
+                 .help("Foreground pixel value for generated patterns")
+                 .takes_value(true)
+                 .value_name("NUM")
+                 .default_value("32767"))
+        .arg(Arg::with_name(arg::CROP)
+                 .long("crop")
+                 .help("Discard everything outside the given region of the \
+                        input image before correcting it")
+                 .takes_value(true)
+                 .value_name("X,Y,W,H")
+                 .validator(|s| parse_region(&s).map(|_| ())))
+        .arg(Arg::with_name(arg::ORIENT)
+                 .long("orient")
+                 .help("Mirror or rotate (clockwise) the corrected image")
+                 .takes_value(true)
+                 .value_name("TRANSFORM")
+                 .possible_values(&[orientation::FLIP_H,
+                                    orientation::FLIP_V,
+                                    orientation::ROT_90,
+                                    orientation::ROT_180,
+                                    orientation::ROT_270]))
+        .arg(Arg::with_name(arg::RESIZE)
+                 .long("resize")
+                 .help("Resample the output image to the given size")
+                 .takes_value(true)
+                 .value_name("WxH")
+                 .validator(|s| parse_dimensions(&s).map(|_| ())))
+        .arg(Arg::with_name(arg::INTERPOLATION)
+                 .long("interpolation")
+                 .help("How to synthesize new pixel values when resampling. \
+                        Requires --resize")
+                 .takes_value(true)
+                 .value_name("METHOD")
+                 .possible_values(&[interpolation::NEAREST,
+                                    interpolation::BILINEAR])
+                 .default_value(interpolation::BILINEAR))
+        .arg(Arg::with_name(arg::SUPERSAMPLE)
+                 .long("supersample")
+                 .help("Average an NxN grid of samples for each output pixel \
+                        when resampling. Reduces aliasing, but is N squared \
+                        times slower. Requires --resize")
+                 .takes_value(true)
+                 .value_name("N")
+                 .validator(is_positive_int)
+                 .default_value("1"))
+        .arg(Arg::with_name(arg::COMPARE)
+                 .long("compare")
+                 .help("Compare the processed image against a reference \
+                        image of the same size and format, and print the \
+                        differences")
+                 .takes_value(true)
+                 .value_name("FILE")
+                 .conflicts_with(arg::OUTPUT_DIR))
+        .arg(Arg::with_name(arg::TOLERANCE)
+                 .long("tolerance")
+                 .help("Fail the comparison if any pixel differs from the \
+                        reference by more than this")
+                 .takes_value(true)
+                 .value_name("NUM")
+                 .default_value("0"))
+        .arg(Arg::with_name(arg::STRICT)
+                 .long("strict")
+                 .help("Fail if there is more data on stdin than the image \
+                        dimensions call for"))
 }
 
 pub fn parse() -> Options {
-    let m = build_cmd_line().get_matches();
+    parse_from(env::args_os()).unwrap_or_else(|e| e.exit())
+}
+
+fn parse_from<I, T>(args: I) -> clap::Result<Options>
+    where I: IntoIterator<Item = T>,
+          T: Into<OsString> + Clone
+{
+    let m = build_cmd_line().get_matches_from_safe(args)?;
+
+    let pixel_value = |n| value_t!(m, n, isize).map(|v| v * PX);
+
+    let float_value = |n| value_t!(m, n, f64);
+
+    let action = match m.value_of(arg::GENERATE) {
+        Some(pattern::CHECKERBOARD) => {
+            Action::Generate(Pattern::Checkerboard {
+                                 cell: pixel_value(arg::CELL)?,
+                                 low: float_value(arg::LOW)?,
+                                 high: float_value(arg::HIGH)?,
+                             })
+        }
+        Some(pattern::GRADIENT) => {
+            let axis = match m.value_of(arg::AXIS) {
+                Some("y") => Axis::Y,
+                _ => Axis::X,
+            };
+            Action::Generate(Pattern::Gradient(axis))
+        }
+        Some(pattern::GRID) => {
+            Action::Generate(Pattern::Grid {
+                                 spacing: pixel_value(arg::CELL)?,
+                                 line: float_value(arg::HIGH)?,
+                                 background: float_value(arg::LOW)?,
+                             })
+        }
+        _ => {
+            let images = m.values_of(arg::IMAGE)
+                .map(|v| v.collect::<Vec<_>>())
+                .unwrap_or_else(Vec::new);
+            let paths = images.iter()
+                .filter(|p| **p != STD_STREAM)
+                .map(|p| expand_filename(p).unwrap())
+                .collect::<Vec<_>>();
 
-    let pixel_value =
-        |n| value_t!(m, n, isize).unwrap_or_else(|e| e.exit()) * PX;
+            let is_batch = (images.len() > 1) ||
+                           m.is_present(arg::OUTPUT_DIR) ||
+                           paths.iter().any(|p| p.is_dir());
 
-    let img = m.value_of(arg::IMAGE)
-        .and_then(|p| expand_filename(p).ok())
-        .unwrap();
+            if is_batch {
+                if paths.len() != images.len() {
+                    let msg = "stdin can't be used as part of a batch";
+                    let kind = ErrorKind::ArgumentConflict;
+                    return Err(clap::Error::with_description(msg, kind));
+                }
+                if images.len() > 1 && m.is_present(arg::OUTPUT) {
+                    let msg = "--output can't be used with more than one \
+                               input; use --output-dir instead";
+                    let kind = ErrorKind::ArgumentConflict;
+                    return Err(clap::Error::with_description(msg, kind));
+                }
+                if !m.is_present(arg::OUTPUT_DIR) {
+                    let msg = "a batch needs --output-dir to write its \
+                               results to";
+                    let kind = ErrorKind::MissingRequiredArgument;
+                    return Err(clap::Error::with_description(msg, kind));
+                }
+                Action::Batch(paths)
+            } else {
+                match paths.into_iter().next() {
+                    Some(p) => Action::Correct(Input::File(p)),
+                    None => Action::Correct(Input::Stdin),
+                }
+            }
+        }
+    };
 
-    Options {
-        input: img,
-        width: pixel_value(arg::WIDTH),
-        height: pixel_value(arg::HEIGHT),
+    let output = m.value_of(arg::OUTPUT).map(|p| match p {
+        STD_STREAM => Output::Stdout,
+        _ => Output::File(expand_filename(p).unwrap()),
+    });
+
+    let output_dir = m.value_of(arg::OUTPUT_DIR)
+        .map(|p| expand_filename(p).unwrap());
+
+    let width = pixel_value(arg::WIDTH)?;
+    let height = pixel_value(arg::HEIGHT)?;
+
+    let parse_format = |s| match s {
+        format::I32 => PixelFormat::I32,
+        format::F32 => PixelFormat::F32,
+        _ => PixelFormat::I16,
+    };
+    let format = m.value_of(arg::FORMAT)
+        .map_or(PixelFormat::I16, &parse_format);
+    let output_format = m.value_of(arg::OUTPUT_FORMAT).map(&parse_format);
+
+    let convert_mode = match m.value_of(arg::CONVERT_MODE) {
+        Some(convert_mode::CAST) => ConvertMode::Cast,
+        Some(convert_mode::RESCALE) => ConvertMode::Rescale,
+        _ => ConvertMode::Saturate,
+    };
+
+    // the crop region is checked in isolation by the argument's validator,
+    // but only here can we check that it fits inside the image
+    let crop = m.value_of(arg::CROP).map(|s| parse_region(s).unwrap());
+    if let Some(r) = crop {
+        let (x, y) = r.origin;
+        let (w, h) = r.size;
+        if (x + w > width) || (y + h > height) {
+            let msg = format!("crop region {},{},{},{} does not fit inside a \
+                               {} x {} image",
+                              x / PX,
+                              y / PX,
+                              w / PX,
+                              h / PX,
+                              width / PX,
+                              height / PX);
+            return Err(clap::Error::with_description(&msg,
+                                                     ErrorKind::ValueValidation));
+        }
+    }
+
+    let orient = match m.value_of(arg::ORIENT) {
+        Some(orientation::FLIP_H) => Some(Orientation::FlipHorizontal),
+        Some(orientation::FLIP_V) => Some(Orientation::FlipVertical),
+        Some(orientation::ROT_90) => Some(Orientation::Rotate90),
+        Some(orientation::ROT_180) => Some(Orientation::Rotate180),
+        Some(orientation::ROT_270) => Some(Orientation::Rotate270),
+        _ => None,
+    };
+
+    // already checked by the argument's validator
+    let resize = m.value_of(arg::RESIZE)
+        .map(|s| parse_dimensions(s).unwrap());
+
+    // these have defaults, so clap's `requires` can't check them
+    let dependencies = [(arg::INTERPOLATION, arg::RESIZE),
+                        (arg::SUPERSAMPLE, arg::RESIZE),
+                        (arg::CONVERT_MODE, arg::OUTPUT_FORMAT)];
+    for &(name, needs) in &dependencies {
+        if m.occurrences_of(name) > 0 && !m.is_present(needs) {
+            let msg = format!("--{} has no effect without --{}", name, needs);
+            let kind = ErrorKind::MissingRequiredArgument;
+            return Err(clap::Error::with_description(&msg, kind));
+        }
+    }
+
+    let interpolation = match m.value_of(arg::INTERPOLATION) {
+        Some(interpolation::NEAREST) => Interpolation::NearestNeighbour,
+        _ => Interpolation::Bilinear,
+    };
+
+    Ok(Options {
+           action: action,
+           output: output,
+           output_dir: output_dir,
+           width: width,
+           height: height,
+           format: format,
+           crop: crop,
+           orient: orient,
+           resize: resize,
+           interpolation: interpolation,
+           supersample: value_t!(m, arg::SUPERSAMPLE, usize)?,
+           compare: m.value_of(arg::COMPARE)
+               .map(|p| expand_filename(p).unwrap()),
+           tolerance: float_value(arg::TOLERANCE)?,
+           output_format: output_format,
+           convert_mode: convert_mode,
+           strict: m.is_present(arg::STRICT),
+       })
+}
+
+#[cfg(test)]
+mod test_parse {
+    use super::{parse_from, Action, Input, Output};
+    use convert::ConvertMode;
+    use distort::Interpolation;
+    use image::PixelFormat;
+    use std::path::PathBuf;
+    use transform::Orientation;
+    use units::PX;
+
+    fn args(extra: &[&str]) -> Vec<String> {
+        let mut v = vec!["firkin", "-i", "/tmp/input.raw", "-w", "64", "-h",
+                         "32"];
+        v.extend_from_slice(extra);
+        v.into_iter().map(String::from).collect()
+    }
+
+    #[test]
+    fn crop_flush_against_bottom_right() {
+        let opts = parse_from(args(&["--crop", "60,30,4,2"])).unwrap();
+        let r = opts.crop.unwrap();
+        assert_eq!(r.origin, (60isize * PX, 30isize * PX));
+        assert_eq!(r.size, (4isize * PX, 2isize * PX));
+    }
+
+    #[test]
+    fn crop_outside_image_is_an_error() {
+        assert!(parse_from(args(&["--crop", "60,30,5,2"])).is_err());
+        assert!(parse_from(args(&["--crop", "60,30,4,3"])).is_err());
+    }
+
+    #[test]
+    fn malformed_crop_is_an_error() {
+        assert!(parse_from(args(&["--crop", "1,2,3"])).is_err());
+        assert!(parse_from(args(&["--crop", "0,0,0,0"])).is_err());
+        assert!(parse_from(args(&["--crop", "left"])).is_err());
+    }
+
+    #[test]
+    fn orientation() {
+        let opts = parse_from(args(&["--orient", "rot180"])).unwrap();
+        assert_eq!(opts.orient, Some(Orientation::Rotate180));
+
+        let opts = parse_from(args(&["--orient", "fliph"])).unwrap();
+        assert_eq!(opts.orient, Some(Orientation::FlipHorizontal));
+
+        assert!(parse_from(args(&["--orient", "upside-down"])).is_err());
+    }
+
+    #[test]
+    fn pixel_format() {
+        let opts = parse_from(args(&[])).unwrap();
+        assert_eq!(opts.format, PixelFormat::I16);
+
+        let opts = parse_from(args(&["--format", "f32"])).unwrap();
+        assert_eq!(opts.format, PixelFormat::F32);
+
+        let opts = parse_from(args(&["--format", "i32"])).unwrap();
+        assert_eq!(opts.format, PixelFormat::I32);
+
+        assert!(parse_from(args(&["--format", "u8"])).is_err());
+    }
+
+    #[test]
+    fn std_streams() {
+        let v = vec!["firkin", "-w", "64", "-h", "32", "-o", "-"];
+        let opts = parse_from(v).unwrap();
+        match opts.action {
+            Action::Correct(input) => assert_eq!(input, Input::Stdin),
+            _ => panic!("expected a correction"),
+        }
+        assert_eq!(opts.output, Some(Output::Stdout));
+        assert!(!opts.strict);
+
+        let v = vec!["firkin", "-i", "-", "--strict"];
+        let opts = parse_from(v).unwrap();
+        match opts.action {
+            Action::Correct(input) => assert_eq!(input, Input::Stdin),
+            _ => panic!("expected a correction"),
+        }
+        assert_eq!(opts.output, None);
+        assert!(opts.strict);
+    }
+
+    #[test]
+    fn files() {
+        let opts = parse_from(args(&["-o", "/tmp/output.raw"])).unwrap();
+        match opts.action {
+            Action::Correct(input) => {
+                assert_eq!(input, Input::File("/tmp/input.raw".into()))
+            }
+            _ => panic!("expected a correction"),
+        }
+        assert_eq!(opts.output, Some(Output::File("/tmp/output.raw".into())));
+    }
+
+    #[test]
+    fn batches() {
+        let v = vec!["firkin",
+                     "-i",
+                     "/tmp/a.raw",
+                     "-i",
+                     "/tmp/b.raw",
+                     "--output-dir",
+                     "/tmp/out"];
+        match parse_from(v).unwrap().action {
+            Action::Batch(inputs) => {
+                assert_eq!(inputs,
+                           vec![PathBuf::from("/tmp/a.raw"),
+                                PathBuf::from("/tmp/b.raw")])
+            }
+            _ => panic!("expected a batch"),
+        }
+
+        let v = vec!["firkin", "-i", "/tmp/a.raw", "--output-dir", "/tmp/out"];
+        let opts = parse_from(v).unwrap();
+        match opts.action {
+            Action::Batch(inputs) => {
+                assert_eq!(inputs, vec![PathBuf::from("/tmp/a.raw")])
+            }
+            _ => panic!("expected a batch"),
+        }
+        assert_eq!(opts.output_dir, Some(PathBuf::from("/tmp/out")));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn stdin_is_not_allowed_in_a_batch() {
+        let v = vec!["firkin",
+                     "-i",
+                     "/tmp/a.raw",
+                     "-i",
+                     "-",
+                     "--output-dir",
+                     "/tmp/out"];
+        assert!(parse_from(v).is_err());
+    }
+
+    #[test]
+    fn a_batch_requires_an_output_dir() {
+        let v = vec!["firkin", "-i", "/tmp/a.raw", "-i", "/tmp/b.raw"];
+        assert!(parse_from(v).is_err());
+
+        let v = vec!["firkin", "-i", "/tmp"];
+        assert!(parse_from(v).is_err());
+    }
+
+    #[test]
+    fn output_is_not_allowed_with_several_inputs() {
+        let v = vec!["firkin",
+                     "-i",
+                     "/tmp/a.raw",
+                     "-i",
+                     "/tmp/b.raw",
+                     "-o",
+                     "/tmp/out.raw"];
+        assert!(parse_from(v).is_err());
+    }
+
+    #[test]
+    fn output_and_output_dir_conflict() {
+        assert!(parse_from(args(&["-o", "out.raw", "--output-dir", "out"]))
+                    .is_err());
+    }
+
+    #[test]
+    fn supersample() {
+        assert_eq!(parse_from(args(&[])).unwrap().supersample, 1);
+
+        let opts = parse_from(args(&["--resize", "8x4", "--supersample", "4"]))
+            .unwrap();
+        assert_eq!(opts.supersample, 4);
+
+        assert!(parse_from(args(&["--resize", "8x4", "--supersample", "0"]))
+                    .is_err());
+    }
+
+    #[test]
+    fn interpolation() {
+        let opts = parse_from(args(&[])).unwrap();
+        assert_eq!(opts.interpolation, Interpolation::Bilinear);
+
+        let opts = parse_from(args(&["--resize",
+                                     "8x4",
+                                     "--interpolation",
+                                     "nearest"]))
+            .unwrap();
+        assert_eq!(opts.interpolation, Interpolation::NearestNeighbour);
+
+        assert!(parse_from(args(&["--resize",
+                                  "8x4",
+                                  "--interpolation",
+                                  "bicubic"]))
+                    .is_err());
+    }
+
+    #[test]
+    fn interpolation_requires_resize() {
+        assert!(parse_from(args(&["--interpolation", "nearest"])).is_err());
+    }
+
+    #[test]
+    fn supersample_requires_resize() {
+        assert!(parse_from(args(&["--supersample", "4"])).is_err());
+    }
+
+    #[test]
+    fn compare() {
+        let opts = parse_from(args(&[])).unwrap();
+        assert_eq!(opts.compare, None);
+
+        let v = args(&["--compare", "/tmp/ref.raw", "--tolerance", "2.5"]);
+        let opts = parse_from(v).unwrap();
+        assert_eq!(opts.compare, Some(PathBuf::from("/tmp/ref.raw")));
+        assert_eq!(opts.tolerance, 2.5);
+
+        assert!(parse_from(args(&["--compare", "/tmp/ref.raw",
+                                  "--tolerance", "lots"]))
+                    .is_err());
+    }
+
+    #[test]
+    fn output_format() {
+        let opts = parse_from(args(&[])).unwrap();
+        assert_eq!(opts.output_format, None);
+        assert_eq!(opts.convert_mode, ConvertMode::Saturate);
+
+        let v = args(&["--format", "f32", "--output-format", "i16",
+                       "--convert-mode", "rescale"]);
+        let opts = parse_from(v).unwrap();
+        assert_eq!(opts.format, PixelFormat::F32);
+        assert_eq!(opts.output_format, Some(PixelFormat::I16));
+        assert_eq!(opts.convert_mode, ConvertMode::Rescale);
+
+        assert!(parse_from(args(&["--output-format", "u16"])).is_err());
+        assert!(parse_from(args(&["--output-format",
+                                  "i16",
+                                  "--convert-mode",
+                                  "truncate"]))
+                    .is_err());
+    }
+
+    #[test]
+    fn convert_mode_requires_output_format() {
+        assert!(parse_from(args(&["--convert-mode", "rescale"])).is_err());
+    }
+
+    #[test]
+    fn no_crop() {
+        let opts = parse_from(args(&[])).unwrap();
+        assert!(opts.crop.is_none());
+    }
+}
@@ -0,0 +1,187 @@
+use image::{Image, MutableImage, OwnedImage, Pixel};
+
+/// Selects how pixel values are mapped into the destination type when
+/// converting an image between pixel types.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ConvertMode {
+    /// Convert each value as a plain numeric cast would. Values outside the
+    /// range of the destination type wrap around.
+    Cast,
+
+    /// Convert each value directly, clamping values outside the range of the
+    /// destination type to its limits.
+    Saturate,
+
+    /// Linearly rescale the values so that the smallest and largest values in
+    /// the source image map to the ends of the destination type's rescale
+    /// range (see `Pixel::rescale_range`).
+    Rescale,
+}
+
+/// Converts an image to a different pixel type.
+///
+/// Conversions to integral types round to the nearest integer. NaN pixels
+/// have no meaningful value in any mode, so they always become zero.
+pub fn convert<Src, Dst, ImageType>(src: &ImageType,
+                                    mode: ConvertMode)
+                                    -> OwnedImage<Dst>
+    where Src: Pixel,
+          Dst: Pixel,
+          ImageType: Image<Src>
+{
+    let values = src.pixels().iter().map(|p| p.to_f64().unwrap());
+
+    // Work out the linear transform to apply to each pixel value. This is the
+    // identity except when rescaling.
+    let (scale, offset) = match mode {
+        ConvertMode::Rescale => {
+            let (lo, hi) = values.clone()
+                .filter(|v| !v.is_nan())
+                .fold((None, None), |(lo, hi): (Option<f64>, Option<f64>), v| {
+                    (Some(lo.map_or(v, |lo| lo.min(v))),
+                     Some(hi.map_or(v, |hi| hi.max(v))))
+                });
+            match (lo, hi) {
+                (Some(lo), Some(hi)) if hi > lo => {
+                    let (dst_lo, dst_hi) = Dst::rescale_range();
+                    let scale = (dst_hi - dst_lo) / (hi - lo);
+                    (scale, dst_lo - (lo * scale))
+                }
+                // an empty or flat image has no range to stretch, so just
+                // map everything to zero
+                _ => (0.0, 0.0),
+            }
+        }
+        _ => (1.0, 0.0),
+    };
+
+    let (width, height) = src.dimensions();
+    let mut dst = OwnedImage::new(width, height);
+    {
+        let dst_pixels = dst.pixels_mut();
+        for (d, v) in dst_pixels.iter_mut().zip(values) {
+            *d = if v.is_nan() {
+                Dst::zero()
+            } else {
+                let v = (v * scale) + offset;
+                match mode {
+                    ConvertMode::Cast => Dst::from_f64_cast(v),
+                    _ => Dst::from_f64_saturating(v),
+                }
+            };
+        }
+    }
+    dst
+}
+
+#[cfg(test)]
+mod test_convert {
+    use super::{convert, ConvertMode};
+    use image::{Image, OwnedImage};
+    use std::f32;
+    use units::PX;
+
+    fn make_f32_image(values: &[f32]) -> OwnedImage<f32> {
+        let mut img = OwnedImage::<f32>::new((values.len() as isize) * PX,
+                                             1isize * PX);
+        for (x, v) in values.iter().enumerate() {
+            img[((x as isize) * PX, 0isize * PX)] = *v;
+        }
+        img
+    }
+
+    #[test]
+    fn i16_to_f32_and_back_is_lossless() {
+        let mut src = OwnedImage::<i16>::new(256isize * PX, 256isize * PX);
+        for y in 0..256isize {
+            for x in 0..256isize {
+                let v = ((y * 256) + x) - 32768;
+                src[(x * PX, y * PX)] = v as i16;
+            }
+        }
+
+        for mode in vec![ConvertMode::Cast, ConvertMode::Saturate] {
+            let f = convert::<i16, f32, _>(&src, mode);
+            let back = convert::<f32, i16, _>(&f, mode);
+            assert_eq!(back.pixels(), src.pixels());
+        }
+    }
+
+    #[test]
+    fn float_to_int_rounds() {
+        let src = make_f32_image(&[1.4, 1.5, -1.5, 2.49, 2.51]);
+        let expected = [1i16, 2, -2, 2, 3];
+        for mode in vec![ConvertMode::Cast, ConvertMode::Saturate] {
+            let img = convert::<f32, i16, _>(&src, mode);
+            assert_eq!(img.pixels(), &expected);
+        }
+    }
+
+    #[test]
+    fn out_of_range_cast_wraps() {
+        let src = make_f32_image(&[40000.0, -40000.0, 65537.0]);
+        let img = convert::<f32, i16, _>(&src, ConvertMode::Cast);
+        assert_eq!(img.pixels(), &[-25536i16, 25536, 1]);
+    }
+
+    #[test]
+    fn out_of_range_saturate_clamps() {
+        let src = make_f32_image(&[40000.0, -40000.0, 65537.0, 100.0]);
+        let img = convert::<f32, i16, _>(&src, ConvertMode::Saturate);
+        assert_eq!(img.pixels(), &[32767i16, -32768, 32767, 100]);
+    }
+
+    #[test]
+    fn out_of_range_rescale_fits_range() {
+        // -40000 .. 60000 is mapped onto -32768 .. 32767
+        let src = make_f32_image(&[-40000.0, 35000.0, 60000.0]);
+        let img = convert::<f32, i16, _>(&src, ConvertMode::Rescale);
+        assert_eq!(img.pixels(), &[-32768i16, 16383, 32767]);
+    }
+
+    #[test]
+    fn rescale_to_int_uses_the_full_range() {
+        let mut src = OwnedImage::<i16>::new(2isize * PX, 1isize * PX);
+        src[(0isize * PX, 0isize * PX)] = 10;
+        src[(1isize * PX, 0isize * PX)] = 20;
+
+        let img = convert::<i16, i32, _>(&src, ConvertMode::Rescale);
+        assert_eq!(img.pixels(), &[i32::min_value(), i32::max_value()]);
+    }
+
+    #[test]
+    fn rescale_to_float_is_normalised() {
+        let mut src = OwnedImage::<i16>::new(3isize * PX, 1isize * PX);
+        src[(0isize * PX, 0isize * PX)] = 100;
+        src[(1isize * PX, 0isize * PX)] = 150;
+        src[(2isize * PX, 0isize * PX)] = 300;
+
+        let img = convert::<i16, f32, _>(&src, ConvertMode::Rescale);
+        assert_eq!(img.pixels(), &[0.0f32, 0.25, 1.0]);
+    }
+
+    #[test]
+    fn rescaling_a_flat_image_gives_zero() {
+        let src = make_f32_image(&[5.0, 5.0, 5.0]);
+        let img = convert::<f32, i16, _>(&src, ConvertMode::Rescale);
+        assert_eq!(img.pixels(), &[0i16, 0, 0]);
+    }
+
+    #[test]
+    fn nan_becomes_zero() {
+        let src = make_f32_image(&[f32::NAN, 10.0, 20.0]);
+
+        let img = convert::<f32, i16, _>(&src, ConvertMode::Cast);
+        assert_eq!(img.pixels(), &[0i16, 10, 20]);
+
+        let img = convert::<f32, i16, _>(&src, ConvertMode::Saturate);
+        assert_eq!(img.pixels(), &[0i16, 10, 20]);
+
+        // NaN is also ignored when finding the range to rescale
+        let img = convert::<f32, i16, _>(&src, ConvertMode::Rescale);
+        assert_eq!(img.pixels(), &[0i16, -32768, 32767]);
+
+        let img = convert::<f32, f32, _>(&src, ConvertMode::Saturate);
+        assert_eq!(img.pixels(), &[0.0f32, 10.0, 20.0]);
+    }
+}
@@ -1,5 +1,5 @@
 use units::{PX, DistPx, DistPxFrac};
-use image::Image;
+use image::{Image, OwnedImage, Pixel};
 use num;
 
 /// Maps a corrected pixel position in the destination image to an uncorrected
@@ -8,13 +8,57 @@ fn map_dst_pixel(u: DistPx, y: DistPx) -> (DistPxFrac, DistPxFrac) {
     (0.0 * PX, 0.0 * PX)
 }
 
+/// Selects how a pixel value is synthesized when sampling an image at a
+/// sub-pixel location.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Interpolation {
+    /// Use the value of the pixel whose centre is nearest the sample point
+    NearestNeighbour,
+
+    /// Blend the four pixels surrounding the sample point
+    Bilinear,
+}
+
+/// Samples a sub-pixel point on the source image, returning the synthesized
+/// value before it is converted back into the pixel type.
+fn interpolate<PixelType, ImageType>(i: &ImageType,
+                                     u: DistPxFrac,
+                                     v: DistPxFrac,
+                                     interp: Interpolation)
+                                     -> f64
+    where PixelType: Pixel,
+          ImageType: Image<PixelType>
+{
+    match interp {
+        Interpolation::NearestNeighbour => nearest(i, u, v),
+        Interpolation::Bilinear => bilinear(i, u, v),
+    }
+}
+
+/// Samples a sub-pixel point on the source image by picking the pixel
+/// nearest to it.
+fn nearest<PixelType, ImageType>(i: &ImageType,
+                                 u: DistPxFrac,
+                                 v: DistPxFrac)
+                                 -> f64
+    where PixelType: Pixel,
+          ImageType: Image<PixelType>
+{
+    let (x, y) = ((u / PX).round() as isize * PX,
+                  (v / PX).round() as isize * PX);
+    pixel_or_black(i, x, y)
+}
+
 /// Samples a sub-pixel point on the source image by synthesizing a new pixel
 /// via bilinear filtering.
-fn sample_image<ImageType>(i: &ImageType, u: DistPxFrac, v: DistPxFrac) -> i16
-    where ImageType: Image<i16>
+fn bilinear<PixelType, ImageType>(i: &ImageType,
+                                  u: DistPxFrac,
+                                  v: DistPxFrac)
+                                  -> f64
+    where PixelType: Pixel,
+          ImageType: Image<PixelType>
 {
     let one = DistPx::new(1);
-    let max_value = i16::max_value() as f64;
 
     // +-------+-------+
     // |A      |B      |
@@ -50,30 +94,116 @@ fn sample_image<ImageType>(i: &ImageType, u: DistPxFrac, v: DistPxFrac) -> i16
     let d = pixel_or_black(i, x + one, y + one);
 
     // combine the pixels together to synthesize a new pixel value
-    let new_pixel =
-        ((a * col_0_contrib + b * col_1_contrib) * row_0_contrib) +
-        ((c * col_0_contrib + d * col_1_contrib) * row_1_contrib);
-
-    num::clamp(new_pixel, 0.0, max_value).round() as i16
+    ((a * col_0_contrib + b * col_1_contrib) * row_0_contrib) +
+    ((c * col_0_contrib + d * col_1_contrib) * row_1_contrib)
 }
 
 #[inline]
-fn pixel_or_black<ImageType>(i: &ImageType, x: DistPx, y: DistPx) -> f64
-    where ImageType: Image<i16>
+fn pixel_or_black<PixelType, ImageType>(i: &ImageType,
+                                        x: DistPx,
+                                        y: DistPx)
+                                        -> f64
+    where PixelType: Pixel,
+          ImageType: Image<PixelType>
 {
     let zero = DistPx::new(0isize);
     let (width, height) = i.dimensions();
     if (x < zero) || (y < zero) || (x >= width) || (y >= height) {
         0.0
     } else {
-        i[(x, y)] as f64
+        i[(x, y)].to_f64().unwrap()
     }
 }
 
+/// Builds a new image of the given size by mapping each destination pixel
+/// back onto the source image with `map` and sampling the source there.
+///
+/// If `supersample` is greater than one, each destination pixel is divided
+/// into a `supersample` x `supersample` grid of sub-pixels, each of which is
+/// mapped and sampled separately, and the pixel's value is the average of
+/// them all. This reduces aliasing where a destination pixel covers more than
+/// one source pixel, at the cost of `supersample` squared times as many
+/// samples. A `supersample` of one samples each pixel centre once.
+///
+/// Every destination pixel is computed independently of the others.
+pub fn remap<PixelType, ImageType, F>(src: &ImageType,
+                                      width: DistPx,
+                                      height: DistPx,
+                                      map: F,
+                                      interp: Interpolation,
+                                      supersample: usize)
+                                      -> OwnedImage<PixelType>
+    where PixelType: Pixel,
+          ImageType: Image<PixelType>,
+          F: Fn(DistPxFrac, DistPxFrac) -> (DistPxFrac, DistPxFrac)
+{
+    assert!(supersample > 0, "supersample factor must be positive");
+
+    // the offsets of the sub-pixel centres from the pixel centre, along
+    // either axis
+    let n = supersample as f64;
+    let offsets = (0..supersample)
+        .map(|i| ((i as f64 + 0.5) / n) - 0.5)
+        .collect::<Vec<_>>();
+    let weight = 1.0 / (n * n);
+
+    let mut dst = OwnedImage::new(width, height);
+    for y in 0..height / PX {
+        for x in 0..width / PX {
+            // accumulate at full precision, and only round & clamp the final
+            // value once
+            let mut total = 0.0;
+            for dy in &offsets {
+                for dx in &offsets {
+                    let (u, v) = map((x as f64 + dx) * PX,
+                                     (y as f64 + dy) * PX);
+                    total += interpolate(src, u, v, interp);
+                }
+            }
+            dst[(x * PX, y * PX)] = PixelType::from_f64_saturating(total *
+                                                                   weight);
+        }
+    }
+    dst
+}
+
+/// Resamples an image to a new size.
+///
+/// Each destination pixel is mapped back onto the source image by the ratio
+/// of the image sizes, treating pixel coordinates as the pixel centres so that
+/// (for example) a 2x upscale doesn't shift the image by half a pixel. Sample
+/// points are clamped to the source image so that the edges of the result
+/// aren't darkened by blending with the black outside the image.
+///
+/// Downscaling by more than a factor of two with a `supersample` of one will
+/// skip over source pixels and alias; see `remap` for how supersampling
+/// helps.
+pub fn resize<PixelType, ImageType>(src: &ImageType,
+                                    new_width: DistPx,
+                                    new_height: DistPx,
+                                    interp: Interpolation,
+                                    supersample: usize)
+                                    -> OwnedImage<PixelType>
+    where PixelType: Pixel,
+          ImageType: Image<PixelType>
+{
+    let (src_width, src_height) = src.dimensions();
+    let (sw, sh) = ((src_width / PX) as f64, (src_height / PX) as f64);
+    let (x_scale, y_scale) = (sw / (new_width / PX) as f64,
+                              sh / (new_height / PX) as f64);
+
+    let map = |x: DistPxFrac, y: DistPxFrac| {
+        let u = num::clamp(((x / PX + 0.5) * x_scale) - 0.5, 0.0, sw - 1.0);
+        let v = num::clamp(((y / PX + 0.5) * y_scale) - 0.5, 0.0, sh - 1.0);
+        (u * PX, v * PX)
+    };
+    remap(src, new_width, new_height, map, interp, supersample)
+}
+
 #[cfg(test)]
 mod test_sampling {
-    use super::sample_image;
-    use image::{self, OwnedImage, MutableImage};
+    use super::bilinear;
+    use image::{self, OwnedImage, MutableImage, Pixel};
     use units::{self, PX, DistPx};
 
     #[test]
@@ -90,7 +220,7 @@ mod test_sampling {
         let mut img = OwnedImage::<i16>::new(3isize * PX, 3isize * PX);
         img.fill(0);
         img[(1isize * PX, 1isize * PX)] = 2048;
-        let rval = sample_image(&img, 1.0 * PX, 1.0 * PX);
+        let rval = i16::from_f64_saturating(bilinear(&img, 1.0 * PX, 1.0 * PX));
         assert_eq!(rval, 2048)
     }
 
@@ -112,7 +242,7 @@ mod test_sampling {
         img[(1isize * PX, 2isize * PX)] = 48;
         img[(2isize * PX, 2isize * PX)] = 48;
 
-        let rval = sample_image(&img, 1.5 * PX, 1.5 * PX);
+        let rval = i16::from_f64_saturating(bilinear(&img, 1.5 * PX, 1.5 * PX));
         assert_eq!(rval, 48)
     }
 
@@ -141,7 +271,8 @@ mod test_sampling {
                               (1.00f64 * PX, 1024)];
 
         for (offset, expected) in test_cases {
-            let rval = sample_image(&img, offset, 1.0f64 * PX);
+            let rval =
+                i16::from_f64_saturating(bilinear(&img, offset, 1.0f64 * PX));
             assert_eq!(rval, expected);
         }
     }
@@ -171,8 +302,180 @@ mod test_sampling {
                               (1.00f64 * PX, 1024)];
 
         for (offset, expected) in test_cases {
-            let rval = sample_image(&img, 1.0f64 * PX, offset);
+            let rval =
+                i16::from_f64_saturating(bilinear(&img, 1.0f64 * PX, offset));
             assert_eq!(rval, expected);
         }
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod test_resize {
+    use super::{resize, Interpolation};
+    use image::{Image, OwnedImage, MutableImage};
+    use units::PX;
+
+    #[test]
+    fn identity_resize_is_an_exact_copy() {
+        let (w, h) = (13isize, 7isize);
+        let mut img = OwnedImage::<f32>::new(w * PX, h * PX);
+        for y in 0..h {
+            for x in 0..w {
+                img[(x * PX, y * PX)] = ((y * 100) + x) as f32 + 0.3;
+            }
+        }
+
+        for interp in vec![Interpolation::NearestNeighbour,
+                           Interpolation::Bilinear] {
+            let r = resize(&img, w * PX, h * PX, interp, 1);
+            assert_eq!(r.dimensions(), img.dimensions());
+            assert_eq!(r.pixels(), img.pixels());
+        }
+    }
+
+    #[test]
+    fn upscaling_a_step_edge_produces_a_ramp() {
+        //  source: |  0  |  0  | 100 | 100 |
+        //
+        //  dest:   | 0 | 0 | 0 |25 |75 |100|100|100|
+        //
+        // Destination pixel centres land a quarter of a source pixel either
+        // side of the source pixel centres, so the edge is blended over the
+        // two pixels adjacent to it and the ramp stays centred.
+
+        let mut img = OwnedImage::<i16>::new(4isize * PX, 1isize * PX);
+        img[(2isize * PX, 0isize * PX)] = 100;
+        img[(3isize * PX, 0isize * PX)] = 100;
+
+        let r = resize(&img,
+                       8isize * PX,
+                       2isize * PX,
+                       Interpolation::Bilinear,
+                       1);
+        let expected = [0i16, 0, 0, 25, 75, 100, 100, 100];
+        for y in 0..2isize {
+            for x in 0..8isize {
+                assert_eq!(r[(x * PX, y * PX)], expected[x as usize]);
+            }
+        }
+    }
+
+    #[test]
+    fn edges_are_not_darkened() {
+        let mut img = OwnedImage::<i16>::new(3isize * PX, 3isize * PX);
+        img.fill(1000);
+
+        let r = resize(&img,
+                       7isize * PX,
+                       5isize * PX,
+                       Interpolation::Bilinear,
+                       1);
+        assert!(r.pixels().iter().all(|p| *p == 1000));
+    }
+
+    #[test]
+    fn non_integer_ratios_stay_in_bounds() {
+        let img = OwnedImage::<i16>::checkerboard(7isize * PX,
+                                                  5isize * PX,
+                                                  1isize * PX,
+                                                  0,
+                                                  1000);
+        for &(w, h) in &[(3isize, 2isize), (11, 13), (6, 4), (1, 1)] {
+            for interp in vec![Interpolation::NearestNeighbour,
+                               Interpolation::Bilinear] {
+                let r = resize(&img, w * PX, h * PX, interp, 1);
+                assert_eq!(r.dimensions(), (w * PX, h * PX));
+                assert!(r.pixels().iter().all(|p| *p >= 0 && *p <= 1000));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_remap {
+    use super::{interpolate, remap, resize, Interpolation};
+    use image::{Image, MutableImage, OwnedImage, Pixel};
+    use units::{PX, DistPxFrac};
+
+    /// A barrel-like mapping that increasingly stretches the image towards
+    /// the bottom-right corner, so that destination pixels there cover
+    /// several source pixels.
+    fn stretch(x: DistPxFrac, y: DistPxFrac) -> (DistPxFrac, DistPxFrac) {
+        let (x, y) = (x / PX, y / PX);
+        let r2 = ((x * x) + (y * y)) / (64.0 * 64.0);
+        let k = 1.0 + (3.0 * r2);
+        ((x * k) * PX, (y * k) * PX)
+    }
+
+    /// Mean squared difference between the bottom-right quadrants of two
+    /// images
+    fn corner_error(a: &OwnedImage<f32>, b: &OwnedImage<f32>) -> f64 {
+        let (w, h) = a.dimensions();
+        let (w, h) = (w / PX, h / PX);
+        let mut total = 0.0;
+        for y in h / 2..h {
+            for x in w / 2..w {
+                let d = (a[(x * PX, y * PX)] - b[(x * PX, y * PX)]) as f64;
+                total += d * d;
+            }
+        }
+        total / ((w / 2) * (h / 2)) as f64
+    }
+
+    #[test]
+    fn single_sample_is_unchanged() {
+        let img = OwnedImage::<i16>::checkerboard(64isize * PX,
+                                                  64isize * PX,
+                                                  3isize * PX,
+                                                  0,
+                                                  1000);
+        for interp in vec![Interpolation::NearestNeighbour,
+                           Interpolation::Bilinear] {
+            let r = remap(&img, 48isize * PX, 48isize * PX, stretch, interp, 1);
+            for y in 0..48isize {
+                for x in 0..48isize {
+                    let (u, v) = stretch((x as f64) * PX, (y as f64) * PX);
+                    let expected =
+                        i16::from_f64_saturating(interpolate(&img,
+                                                             u,
+                                                             v,
+                                                             interp));
+                    assert_eq!(r[(x * PX, y * PX)], expected);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn supersampling_reduces_aliasing() {
+        let img = OwnedImage::<f32>::checkerboard(256isize * PX,
+                                                  256isize * PX,
+                                                  1isize * PX,
+                                                  0.0,
+                                                  1000.0);
+        let (w, h) = (64isize * PX, 64isize * PX);
+        let interp = Interpolation::Bilinear;
+
+        let reference = remap(&img, w, h, stretch, interp, 16);
+        let single = remap(&img, w, h, stretch, interp, 1);
+        let super4 = remap(&img, w, h, stretch, interp, 4);
+
+        let (e1, e4) = (corner_error(&single, &reference),
+                        corner_error(&super4, &reference));
+        assert!(e4 * 10.0 < e1,
+                "expected supersampling to reduce the error, got {} vs {}",
+                e4,
+                e1);
+    }
+
+    #[test]
+    fn supersampled_flat_image_is_unchanged() {
+        let mut img = OwnedImage::<i16>::new(8isize * PX, 8isize * PX);
+        img.fill(777);
+        let r = resize(&img,
+                       5isize * PX,
+                       3isize * PX,
+                       Interpolation::Bilinear,
+                       3);
+        assert!(r.pixels().iter().all(|p| *p == 777));
+    }
+}
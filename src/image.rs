@@ -1,35 +1,157 @@
 use std::path::Path;
-use std::io::{Error, Result};
+use std::io::{Error, ErrorKind, Read, Result, Write};
 use std::ops;
 
 use memmap::{Mmap, Protection};
-use num::{FromPrimitive, Num};
+use num::{self, Bounded, FromPrimitive, Num, ToPrimitive};
 use units::{DistPx, PX};
 
-pub trait Pixel: Num + Sized + Copy + FromPrimitive {
-    #[cfg(test)]
+pub trait Pixel
+    : Num + Sized + Copy + PartialOrd + Bounded + FromPrimitive + ToPrimitive {
+    /// Fetches the in-memory representation of the pixel, as it would appear
+    /// in a raw image file.
     fn bytes<'a>(&'a self) -> &'a [u8];
+
+    /// Converts a synthesized (e.g. interpolated) value back into a pixel,
+    /// rounding integral types to the nearest value and saturating at the
+    /// limits of the pixel type.
+    fn from_f64_saturating(v: f64) -> Self;
+
+    /// Converts a value into a pixel the way a plain `as` cast would, except
+    /// that values are rounded (rather than truncated) to the nearest integer
+    /// for the integral types. Out-of-range values wrap around.
+    fn from_f64_cast(v: f64) -> Self;
+
+    /// The nominal brightest value of a pixel, as used when computing the
+    /// signal-to-noise ratio of an image. This is the maximum value for the
+    /// integral types, and 1.0 for the floating point types, whose images are
+    /// conventionally normalised.
+    fn peak_value() -> f64;
+
+    /// The range of values an image is stretched over when it is rescaled
+    /// into this pixel type. This is the full range of the integral types,
+    /// and 0.0 to 1.0 for the floating point types.
+    fn rescale_range() -> (f64, f64);
+}
+
+macro_rules! impl_pixel_bytes {
+    ($t:ty) => (
+        fn bytes<'a>(&'a self) -> &'a[u8] {
+            use std::mem;
+            use std::slice;
+
+            // is there a safe way to do this generically?
+            let p : *const $t = self;
+            unsafe {
+                slice::from_raw_parts(p as *const u8, mem::size_of::<$t>())
+            }
+        }
+    )
 }
 
-macro_rules! impl_pixel {
+macro_rules! impl_integer_pixel {
     ($($t:ty),*) => ($(
         impl Pixel for $t {
-            #[cfg(test)]
-            fn bytes<'a>(&'a self) -> &'a[u8] {
-                use std::mem;
-                use std::slice;
-
-                // is there a safe way to do this generically?
-                let p : *const $t = self;
-                unsafe {
-                    slice::from_raw_parts(p as *const u8, mem::size_of::<$t>())
-                }
+            impl_pixel_bytes!($t);
+
+            fn from_f64_saturating(v: f64) -> $t {
+                let (lo, hi) = (<$t>::min_value() as f64,
+                                <$t>::max_value() as f64);
+                num::clamp(v.round(), lo, hi) as $t
+            }
+
+            fn from_f64_cast(v: f64) -> $t {
+                v.round() as i64 as $t
+            }
+
+            fn peak_value() -> f64 {
+                <$t>::max_value() as f64
+            }
+
+            fn rescale_range() -> (f64, f64) {
+                (<$t>::min_value() as f64, <$t>::max_value() as f64)
             }
         }
     )*)
 }
 
-impl_pixel!(i16, i32, f32);
+macro_rules! impl_float_pixel {
+    ($($t:ty),*) => ($(
+        impl Pixel for $t {
+            impl_pixel_bytes!($t);
+
+            fn from_f64_saturating(v: f64) -> $t {
+                let (lo, hi) = (<$t>::min_value() as f64,
+                                <$t>::max_value() as f64);
+                num::clamp(v, lo, hi) as $t
+            }
+
+            fn from_f64_cast(v: f64) -> $t {
+                v as $t
+            }
+
+            fn peak_value() -> f64 {
+                1.0
+            }
+
+            fn rescale_range() -> (f64, f64) {
+                (0.0, 1.0)
+            }
+        }
+    )*)
+}
+
+impl_integer_pixel!(i16, i32);
+impl_float_pixel!(f32);
+
+/// Identifies one of the supported pixel types at runtime, e.g. when the
+/// type of a raw image file is chosen by the user.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PixelFormat {
+    I16,
+    I32,
+    F32,
+}
+
+#[cfg(test)]
+mod test_pixel {
+    use super::Pixel;
+
+    #[test]
+    fn integer_conversion_rounds() {
+        assert_eq!(i16::from_f64_saturating(1.4), 1);
+        assert_eq!(i16::from_f64_saturating(1.5), 2);
+        assert_eq!(i32::from_f64_saturating(-2.6), -3);
+    }
+
+    #[test]
+    fn integer_conversion_saturates() {
+        assert_eq!(i16::from_f64_saturating(40000.0), i16::max_value());
+        assert_eq!(i16::from_f64_saturating(-40000.0), i16::min_value());
+    }
+
+    #[test]
+    fn integer_cast_rounds_and_wraps() {
+        assert_eq!(i16::from_f64_cast(1.5), 2);
+        assert_eq!(i16::from_f64_cast(-1.5), -2);
+        assert_eq!(i16::from_f64_cast(32768.0), i16::min_value());
+        assert_eq!(i16::from_f64_cast(70000.0), 4464);
+    }
+
+    #[test]
+    fn float_conversion_does_not_round() {
+        assert_eq!(f32::from_f64_saturating(1.25), 1.25);
+        assert_eq!(f32::from_f64_saturating(1e300), ::std::f32::MAX);
+    }
+
+    #[test]
+    fn rescale_ranges() {
+        assert_eq!(i16::rescale_range(), (-32768.0, 32767.0));
+        assert_eq!(i32::rescale_range(),
+                   (i32::min_value() as f64, i32::max_value() as f64));
+        assert_eq!(f32::rescale_range(), (0.0, 1.0));
+    }
+}
 
 pub trait Image<PixelType: Pixel>
     : ops::Index<(DistPx, DistPx), Output = PixelType> {
@@ -53,6 +175,43 @@ pub trait MutableImage<PixelType: Pixel>
             *p = v;
         }
     }
+
+    /// Mirrors the image in place. Flipping in both directions is equivalent
+    /// to a 180 degree rotation.
+    fn flip(&mut self, direction: Flip) {
+        let (width, height) = self.dimensions();
+        let (w, h) = ((width / PX) as usize, (height / PX) as usize);
+        if w == 0 {
+            return;
+        }
+
+        let pixels = self.pixels_mut();
+        match direction {
+            Flip::Horizontal => {
+                for row in pixels.chunks_mut(w) {
+                    row.reverse();
+                }
+            }
+            Flip::Vertical => {
+                for y in 0..h / 2 {
+                    let (top, bottom) = (y * w, (h - 1 - y) * w);
+                    for x in 0..w {
+                        pixels.swap(top + x, bottom + x);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Selects the direction in which to mirror an image
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Flip {
+    /// Swap the left and right sides of the image
+    Horizontal,
+
+    /// Swap the top and bottom of the image
+    Vertical,
 }
 
 // ----------------------------------------------------------------------------
@@ -66,6 +225,12 @@ pub struct OwnedImage<PixelType: Pixel> {
     pixels: Vec<PixelType>,
 }
 
+/// The error returned when the amount of pixel data available doesn't match
+/// the declared dimensions of an image
+fn size_mismatch() -> Error {
+    Error::new(ErrorKind::Other, "Unexpected size")
+}
+
 impl<PixelType: Pixel> OwnedImage<PixelType> {
     pub fn new(width: DistPx, height: DistPx) -> OwnedImage<PixelType> {
         let size = ((width / PX) * (height / PX)) as usize;
@@ -75,6 +240,33 @@ impl<PixelType: Pixel> OwnedImage<PixelType> {
             pixels: vec![PixelType::zero(); size],
         }
     }
+
+    /// Reads exactly enough raw pixel data from a stream to fill an image of
+    /// the given dimensions. Any data following the image is left unread.
+    /// Running out of data before the image is full is an error.
+    pub fn from_reader<R: Read>(mut r: R,
+                                width: DistPx,
+                                height: DistPx)
+                                -> Result<OwnedImage<PixelType>> {
+        use std::mem;
+        use std::slice;
+
+        let mut img = OwnedImage::new(width, height);
+        {
+            let len = img.pixels.len() * mem::size_of::<PixelType>();
+            let bytes = unsafe {
+                slice::from_raw_parts_mut(img.pixels.as_mut_ptr() as *mut u8,
+                                          len)
+            };
+            r.read_exact(bytes)
+                .map_err(|e| if e.kind() == ErrorKind::UnexpectedEof {
+                             size_mismatch()
+                         } else {
+                             e
+                         })?;
+        }
+        Ok(img)
+    }
 }
 
 impl<PixelType: Pixel> ops::Index<(DistPx, DistPx)> for OwnedImage<PixelType> {
@@ -149,6 +341,138 @@ mod test_owned_image {
             }
         }
     }
+
+    fn make_raw_data(w: isize, h: isize) -> Vec<u8> {
+        let mut data = Vec::new();
+        for y in 0..h {
+            for x in 0..w {
+                let px = ((1000 * y) + x) as i32;
+                data.extend_from_slice(px.bytes());
+            }
+        }
+        data
+    }
+
+    #[test]
+    fn reading_from_a_stream() {
+        use std::io::Cursor;
+
+        let (w, h) = (16isize, 8isize);
+        let img = OwnedImage::<i32>::from_reader(Cursor::new(make_raw_data(w, h)),
+                                                 w * PX,
+                                                 h * PX)
+            .unwrap();
+
+        assert_eq!(img.dimensions(), (w * PX, h * PX));
+        for y in 0..h {
+            for x in 0..w {
+                assert_eq!(img[(x * PX, y * PX)], ((1000 * y) + x) as i32);
+            }
+        }
+    }
+
+    #[test]
+    fn reading_leaves_trailing_data_unread() {
+        use std::io::{Cursor, Read};
+
+        let (w, h) = (16isize, 8isize);
+        let mut data = make_raw_data(w, h);
+        data.extend_from_slice(&[1, 2, 3]);
+
+        let mut cursor = Cursor::new(data);
+        OwnedImage::<i32>::from_reader(&mut cursor, w * PX, h * PX).unwrap();
+
+        let mut rest = Vec::new();
+        cursor.read_to_end(&mut rest).unwrap();
+        assert_eq!(rest, vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn reading_handles_partial_reads() {
+        use std::io::{self, Read};
+
+        /// A reader that only ever returns a few bytes per read
+        struct Trickle(Vec<u8>, usize);
+        impl Read for Trickle {
+            fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+                let n = *[3, buf.len(), self.0.len() - self.1]
+                             .iter()
+                             .min()
+                             .unwrap();
+                buf[..n].copy_from_slice(&self.0[self.1..self.1 + n]);
+                self.1 += n;
+                Ok(n)
+            }
+        }
+
+        let (w, h) = (16isize, 8isize);
+        let r = Trickle(make_raw_data(w, h), 0);
+        let img = OwnedImage::<i32>::from_reader(r, w * PX, h * PX).unwrap();
+        assert_eq!(img[(15isize * PX, 7isize * PX)], 7015);
+    }
+
+    #[test]
+    fn reading_a_short_stream_is_an_error() {
+        use std::io::Cursor;
+
+        let (w, h) = (16isize, 8isize);
+        let mut data = make_raw_data(w, h);
+        data.pop();
+
+        let r = OwnedImage::<i32>::from_reader(Cursor::new(data), w * PX, h * PX);
+        assert!(r.is_err());
+    }
+
+    #[test]
+    fn reading_an_empty_stream_is_an_error() {
+        use std::io::{Cursor, ErrorKind};
+
+        let r = OwnedImage::<i32>::from_reader(Cursor::new(Vec::new()),
+                                               16isize * PX,
+                                               8isize * PX);
+        assert_eq!(r.err().unwrap().kind(), ErrorKind::Other);
+    }
+
+    fn make_test_image(w: isize, h: isize) -> OwnedImage<i32> {
+        let mut img = OwnedImage::<i32>::new(w * PX, h * PX);
+        for y in 0..h {
+            for x in 0..w {
+                img[(x * PX, y * PX)] = ((1000 * y) + x) as i32;
+            }
+        }
+        img
+    }
+
+    #[test]
+    fn flip_horizontal_in_place() {
+        let mut img = make_test_image(5, 3);
+        img.flip(Flip::Horizontal);
+        for y in 0..3isize {
+            for x in 0..5isize {
+                assert_eq!(img[(x * PX, y * PX)], ((1000 * y) + 4 - x) as i32);
+            }
+        }
+    }
+
+    #[test]
+    fn flip_vertical_in_place() {
+        // odd height, so the middle row must stay put
+        let mut img = make_test_image(5, 3);
+        img.flip(Flip::Vertical);
+        for y in 0..3isize {
+            for x in 0..5isize {
+                assert_eq!(img[(x * PX, y * PX)], ((1000 * (2 - y)) + x) as i32);
+            }
+        }
+    }
+
+    #[test]
+    fn flipping_an_empty_image() {
+        let mut img = OwnedImage::<i32>::new(0isize * PX, 3isize * PX);
+        img.flip(Flip::Horizontal);
+        img.flip(Flip::Vertical);
+        assert_eq!(img.pixels().len(), 0);
+    }
 }
 
 // ----------------------------------------------------------------------------
@@ -172,7 +496,6 @@ impl<'a, PixelType: Pixel + 'a> MemoryMappedImage<'a, PixelType> {
                     -> Result<MemoryMappedImage<PixelType>> {
         use std::mem;
         use std::slice;
-        use std::io::ErrorKind;
 
         debug!("Mapping file: {:?}", path);
         let map = Mmap::open_path(path, Protection::Read)?;
@@ -180,7 +503,7 @@ impl<'a, PixelType: Pixel + 'a> MemoryMappedImage<'a, PixelType> {
         let expected_size = ((width / PX) * (height / PX)) as usize *
                             mem::size_of::<PixelType>();
         if map.len() != expected_size {
-            return Err(Error::new(ErrorKind::Other, "Unexpected size"));
+            return Err(size_mismatch());
         }
 
         let pixels = unsafe {
@@ -306,3 +629,65 @@ mod test_memory_mapped_image {
         assert!(maybe_img.is_err());
     }
 }
+
+// ----------------------------------------------------------------------------
+// Raw file output
+// ----------------------------------------------------------------------------
+
+/// Writes the pixels of an image to a stream in scan-major order, with no
+/// header or padding. This is the same layout `MemoryMappedImage` expects to
+/// read.
+pub fn write_raw<PixelType, ImageType, W>(img: &ImageType,
+                                          w: &mut W)
+                                          -> Result<()>
+    where PixelType: Pixel,
+          ImageType: Image<PixelType>,
+          W: Write
+{
+    for p in img.pixels() {
+        w.write_all(p.bytes())?;
+    }
+    Ok(())
+}
+
+/// Writes an image to a raw file, replacing the file if it already exists.
+pub fn write_file<PixelType, ImageType>(img: &ImageType,
+                                        path: &Path)
+                                        -> Result<()>
+    where PixelType: Pixel,
+          ImageType: Image<PixelType>
+{
+    use std::fs::File;
+    use std::io::BufWriter;
+
+    debug!("Writing file: {:?}", path);
+    let mut w = BufWriter::new(File::create(path)?);
+    write_raw(img, &mut w)?;
+    w.flush()
+}
+
+#[cfg(test)]
+mod test_raw_output {
+    use super::*;
+    use tempfile::NamedTempFile;
+    use units::PX;
+
+    #[test]
+    fn written_files_can_be_mapped() {
+        let (w, h) = (17isize, 9isize);
+        let mut img = OwnedImage::<i16>::new(w * PX, h * PX);
+        for y in 0..h {
+            for x in 0..w {
+                img[(x * PX, y * PX)] = ((100 * y) + x) as i16;
+            }
+        }
+
+        let tmp = NamedTempFile::new().unwrap();
+        write_file(&img, tmp.path()).unwrap();
+
+        let mapped =
+            MemoryMappedImage::<i16>::map_file(tmp.path(), w * PX, h * PX)
+                .unwrap();
+        assert_eq!(mapped.pixels(), img.pixels());
+    }
+}
@@ -13,21 +13,652 @@ extern crate tempfile;
 #[cfg(test)]
 extern crate byteorder;
 
+mod analysis;
+mod batch;
 mod cli;
+mod convert;
 mod units;
 mod image;
 mod distort;
+mod patterns;
+mod transform;
 
-use image::Image;
+use std::fs;
+use std::io::{self, BufWriter, Error, ErrorKind, Read, Write};
+use std::path::PathBuf;
+use std::process;
 
+use cli::{Action, Input, Options, Output, Pattern};
+use image::{Flip, Image, MutableImage, OwnedImage, Pixel, PixelFormat};
+use transform::Orientation;
+use units::DistPx;
+
+/// Converts a pixel value supplied on the command line into the pixel type
+/// being generated.
+fn pixel_value<PixelType: Pixel>(v: f64) -> io::Result<PixelType> {
+    PixelType::from_f64(v).ok_or_else(|| {
+        let msg = format!("Pixel value {} is out of range", v);
+        Error::new(ErrorKind::InvalidInput, msg)
+    })
+}
+
+fn render<PixelType: Pixel>(pattern: &Pattern,
+                            width: DistPx,
+                            height: DistPx)
+                            -> io::Result<OwnedImage<PixelType>> {
+    let img = match *pattern {
+        Pattern::Checkerboard { cell, low, high } => {
+            OwnedImage::checkerboard(width,
+                                     height,
+                                     cell,
+                                     pixel_value(low)?,
+                                     pixel_value(high)?)
+        }
+        Pattern::Gradient(axis) => OwnedImage::gradient(width, height, axis),
+        Pattern::Grid {
+            spacing,
+            line,
+            background,
+        } => {
+            OwnedImage::grid(width,
+                             height,
+                             spacing,
+                             pixel_value(line)?,
+                             pixel_value(background)?)
+        }
+    };
+    Ok(img)
+}
+
+/// Writes the result of processing to the output file, if the user asked
+/// for one.
+fn write_output<PixelType, ImageType>(img: &ImageType,
+                                      opts: &Options)
+                                      -> io::Result<()>
+    where PixelType: Pixel,
+          ImageType: Image<PixelType>
+{
+    match opts.output {
+        Some(Output::File(ref path)) => image::write_file(img, path.as_path()),
+        Some(Output::Stdout) => {
+            let stdout = io::stdout();
+            let mut w = BufWriter::new(stdout.lock());
+            image::write_raw(img, &mut w)?;
+            w.flush()
+        }
+        None => Ok(()),
+    }
+}
+
+/// Compares the processed image against the user's reference image, if they
+/// supplied one, failing if the images differ by more than the tolerance.
+fn compare_output<PixelType, ImageType>(img: &ImageType,
+                                        opts: &Options)
+                                        -> io::Result<()>
+    where PixelType: Pixel,
+          ImageType: Image<PixelType>
+{
+    let reference = match opts.compare {
+        Some(ref path) => path,
+        None => return Ok(()),
+    };
+
+    let (width, height) = img.dimensions();
+    let r = image::MemoryMappedImage::<PixelType>::map_file(reference.as_path(),
+                                                            width,
+                                                            height)?;
+    let stats = analysis::compare(img, &r)?;
+    // stdout may be carrying the image itself
+    eprintln!("{}", stats);
+
+    if stats.max_abs_diff > opts.tolerance {
+        let msg = format!("Image differs from {:?} by more than {}",
+                          reference,
+                          opts.tolerance);
+        return Err(Error::new(ErrorKind::Other, msg));
+    }
+    Ok(())
+}
+
+/// Checks whether a stream has any data left in it after an image has been
+/// read from it. This is only a warning unless the user asked for strict
+/// checking, as some capture tools pad their output.
+fn check_for_trailing_data<R: Read>(r: &mut R, strict: bool) -> io::Result<()> {
+    let mut buf = [0u8; 1];
+    if r.read(&mut buf)? == 0 {
+        return Ok(());
+    }
+
+    if strict {
+        Err(Error::new(ErrorKind::InvalidData, "Trailing data after image"))
+    } else {
+        // not a log message, as the default log level would hide it
+        eprintln!("warning: ignoring trailing data after image");
+        Ok(())
+    }
+}
+
+// ----------------------------------------------------------------------------
+// Processing pipeline
+//
+// Each stage either transforms the image and hands the result on to the next
+// stage, or (if the user hasn't asked for it) passes the image straight
+// through, so that no copies are made for stages that don't run.
+// ----------------------------------------------------------------------------
+
+fn crop_stage<PixelType, ImageType>(img: &ImageType,
+                                    opts: &Options)
+                                    -> io::Result<()>
+    where PixelType: Pixel,
+          ImageType: Image<PixelType>
+{
+    match opts.crop {
+        Some(r) => {
+            debug!("Cropping to {:?}", r);
+            let mut cropped = transform::crop(img, r.origin, r.size);
+
+            // The cropped image is already a private copy, so any flips can
+            // be done in place rather than by making another one
+            let flips: &[Flip] = match opts.orient {
+                Some(Orientation::FlipHorizontal) => &[Flip::Horizontal],
+                Some(Orientation::FlipVertical) => &[Flip::Vertical],
+                Some(Orientation::Rotate180) => {
+                    &[Flip::Horizontal, Flip::Vertical]
+                }
+                _ => return orient_stage(&cropped, opts),
+            };
+            debug!("Reorienting in place with {:?}", flips);
+            for f in flips {
+                cropped.flip(*f);
+            }
+            resize_stage(&cropped, opts)
+        }
+        None => orient_stage(img, opts),
+    }
+}
+
+fn orient_stage<PixelType, ImageType>(img: &ImageType,
+                                      opts: &Options)
+                                      -> io::Result<()>
+    where PixelType: Pixel,
+          ImageType: Image<PixelType>
+{
+    match opts.orient {
+        Some(o) => {
+            debug!("Reorienting with {:?}", o);
+            let oriented = transform::orient(img, o);
+            resize_stage(&oriented, opts)
+        }
+        None => resize_stage(img, opts),
+    }
+}
+
+fn resize_stage<PixelType, ImageType>(img: &ImageType,
+                                      opts: &Options)
+                                      -> io::Result<()>
+    where PixelType: Pixel,
+          ImageType: Image<PixelType>
+{
+    match opts.resize {
+        Some((w, h)) => {
+            debug!("Resizing to {} x {}", w, h);
+            let resized = distort::resize(img,
+                                          w,
+                                          h,
+                                          opts.interpolation,
+                                          opts.supersample);
+            finish(&resized, opts)
+        }
+        None => finish(img, opts),
+    }
+}
+
+/// Converts the processed image to the output pixel type (if the user asked
+/// for a different one), and then writes it out and compares it.
+fn finish<PixelType, ImageType>(img: &ImageType,
+                                opts: &Options)
+                                -> io::Result<()>
+    where PixelType: Pixel,
+          ImageType: Image<PixelType>
+{
+    let mode = opts.convert_mode;
+    match opts.output_format {
+        Some(PixelFormat::I16) => {
+            emit(&convert::convert::<PixelType, i16, _>(img, mode), opts)
+        }
+        Some(PixelFormat::I32) => {
+            emit(&convert::convert::<PixelType, i32, _>(img, mode), opts)
+        }
+        Some(PixelFormat::F32) => {
+            emit(&convert::convert::<PixelType, f32, _>(img, mode), opts)
+        }
+        None => emit(img, opts),
+    }
+}
+
+fn emit<PixelType, ImageType>(img: &ImageType, opts: &Options) -> io::Result<()>
+    where PixelType: Pixel,
+          ImageType: Image<PixelType>
+{
+    write_output(img, opts)?;
+    compare_output(img, opts)
+}
+
+/// Runs the whole pipeline, with all images treated as having the given pixel
+/// type.
+fn run<PixelType: Pixel>(opts: &Options) -> io::Result<()> {
+    match opts.action {
+        Action::Generate(ref pattern) => {
+            // generated patterns go through the same processing as images
+            // read from a file
+            let img = render::<PixelType>(pattern, opts.width, opts.height)?;
+            crop_stage(&img, opts)
+        }
+
+        Action::Batch(ref inputs) => run_batch::<PixelType>(inputs, opts),
+
+        Action::Correct(ref input) => {
+            debug!("Input is: {:?} @ {} x {} ({:?})",
+                   input,
+                   opts.width,
+                   opts.height,
+                   opts.format);
+
+            match *input {
+                Input::File(ref path) => {
+                    let img = image::MemoryMappedImage::<PixelType>::map_file(
+                        path.as_path(),
+                        opts.width,
+                        opts.height)?;
+                    crop_stage(&img, opts)
+                }
+                Input::Stdin => {
+                    let stdin = io::stdin();
+                    let mut r = stdin.lock();
+                    let img = OwnedImage::<PixelType>::from_reader(&mut r,
+                                                                   opts.width,
+                                                                   opts.height)?;
+                    check_for_trailing_data(&mut r, opts.strict)?;
+                    crop_stage(&img, opts)
+                }
+            }
+        }
+    }
+}
+
+/// Runs the pipeline over every file in a batch. Per-file failures are
+/// reported but only turn into an overall failure once the whole batch has
+/// been processed.
+fn run_batch<PixelType: Pixel>(inputs: &[PathBuf],
+                               opts: &Options)
+                               -> io::Result<()> {
+    if let Some(ref dir) = opts.output_dir {
+        fs::create_dir_all(dir)?;
+    }
+
+    let output_dir = opts.output_dir.as_ref().map(|p| p.as_path());
+    let summary = batch::run(inputs, output_dir, |src, dst| {
+        let mut file_opts = opts.clone();
+        file_opts.action = Action::Correct(Input::File(src.to_path_buf()));
+        file_opts.output = dst.map(|p| Output::File(p.to_path_buf()));
+        run::<PixelType>(&file_opts)
+    });
+
+    println!("{}", summary);
+    if summary.failed > 0 {
+        let msg = format!("{} file(s) failed", summary.failed);
+        return Err(Error::new(ErrorKind::Other, msg));
+    }
+    Ok(())
+}
+
+/// Selects the concrete pixel type for the pipeline from the user's options
+fn dispatch(opts: &Options) -> io::Result<()> {
+    match opts.format {
+        PixelFormat::I16 => run::<i16>(opts),
+        PixelFormat::I32 => run::<i32>(opts),
+        PixelFormat::F32 => run::<f32>(opts),
+    }
+}
 
 fn main() {
     env_logger::init().unwrap();
 
-    let f = cli::parse();
-    debug!("Input file is: {:?} @ {} x {}", f.input, f.width, f.height);
+    let opts = cli::parse();
+    if let Err(e) = dispatch(&opts) {
+        error!("{}", e);
+        process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod test_dispatch {
+    use super::dispatch;
+    use cli::{Action, Input, Options, Output, Pattern, Region};
+    use convert::ConvertMode;
+    use distort::Interpolation;
+    use image::{self, Image, MemoryMappedImage, OwnedImage, Pixel,
+                PixelFormat};
+    use patterns::Axis;
+    use tempfile::NamedTempFile;
+    use transform::{self, Orientation};
+    use units::PX;
+
+    fn make_options(input: &NamedTempFile,
+                    output: &NamedTempFile,
+                    format: PixelFormat)
+                    -> Options {
+        Options {
+            action: Action::Correct(Input::File(input.path().to_path_buf())),
+            output: Some(Output::File(output.path().to_path_buf())),
+            output_dir: None,
+            width: 6isize * PX,
+            height: 4isize * PX,
+            format: format,
+            crop: None,
+            orient: Some(Orientation::Rotate180),
+            resize: None,
+            interpolation: Interpolation::Bilinear,
+            supersample: 1,
+            compare: None,
+            tolerance: 0.0,
+            output_format: None,
+            convert_mode: ConvertMode::Saturate,
+            strict: false,
+        }
+    }
+
+    /// Writes a small test image of the given pixel type to a temp file,
+    /// runs it through the pipeline and checks that the output was read and
+    /// written with the same pixel type.
+    fn round_trip<PixelType: Pixel + ::std::fmt::Debug>(format: PixelFormat) {
+        let (w, h) = (6isize, 4isize);
+        let mut src = OwnedImage::<PixelType>::new(w * PX, h * PX);
+        for y in 0..h {
+            for x in 0..w {
+                src[(x * PX, y * PX)] =
+                    PixelType::from_isize((100 * y) + x).unwrap();
+            }
+        }
+
+        let input = NamedTempFile::new().unwrap();
+        let output = NamedTempFile::new().unwrap();
+        image::write_file(&src, input.path()).unwrap();
+
+        dispatch(&make_options(&input, &output, format)).unwrap();
+
+        let result = MemoryMappedImage::<PixelType>::map_file(output.path(),
+                                                              w * PX,
+                                                              h * PX)
+            .unwrap();
+        for y in 0..h {
+            for x in 0..w {
+                assert_eq!(result[(x * PX, y * PX)],
+                           src[((w - 1 - x) * PX, (h - 1 - y) * PX)]);
+            }
+        }
+    }
+
+    #[test]
+    fn i16_pipeline() {
+        round_trip::<i16>(PixelFormat::I16);
+    }
+
+    #[test]
+    fn i32_pipeline() {
+        round_trip::<i32>(PixelFormat::I32);
+    }
+
+    #[test]
+    fn f32_pipeline() {
+        round_trip::<f32>(PixelFormat::F32);
+    }
+
+    #[test]
+    fn output_format_conversion() {
+        let mut src = OwnedImage::<f32>::new(6isize * PX, 4isize * PX);
+        src[(0isize * PX, 0isize * PX)] = 1.75;
+        src[(5isize * PX, 3isize * PX)] = -1e6;
+
+        let input = NamedTempFile::new().unwrap();
+        let output = NamedTempFile::new().unwrap();
+        image::write_file(&src, input.path()).unwrap();
+
+        let mut opts = make_options(&input, &output, PixelFormat::F32);
+        opts.output_format = Some(PixelFormat::I16);
+        dispatch(&opts).unwrap();
+
+        // the output is rotated by 180 degrees and written as i16
+        let result = MemoryMappedImage::<i16>::map_file(output.path(),
+                                                        6isize * PX,
+                                                        4isize * PX)
+            .unwrap();
+        assert_eq!(result[(5isize * PX, 3isize * PX)], 2);
+        assert_eq!(result[(0isize * PX, 0isize * PX)], i16::min_value());
+    }
+
+    #[test]
+    fn cropped_images_are_reoriented() {
+        let (w, h) = (6isize, 4isize);
+        let mut src = OwnedImage::<i16>::new(w * PX, h * PX);
+        for y in 0..h {
+            for x in 0..w {
+                src[(x * PX, y * PX)] = ((100 * y) + x) as i16;
+            }
+        }
+
+        let input = NamedTempFile::new().unwrap();
+        image::write_file(&src, input.path()).unwrap();
+
+        let origin = (1isize * PX, 1isize * PX);
+        let size = (4isize * PX, 2isize * PX);
+        let cropped = transform::crop(&src, origin, size);
+
+        for &o in &[Orientation::FlipHorizontal,
+                    Orientation::FlipVertical,
+                    Orientation::Rotate90,
+                    Orientation::Rotate180,
+                    Orientation::Rotate270] {
+            let output = NamedTempFile::new().unwrap();
+            let mut opts = make_options(&input, &output, PixelFormat::I16);
+            opts.crop = Some(Region {
+                                 origin: origin,
+                                 size: size,
+                             });
+            opts.orient = Some(o);
+            dispatch(&opts).unwrap();
+
+            let expected = transform::orient(&cropped, o);
+            let (ew, eh) = expected.dimensions();
+            let result =
+                MemoryMappedImage::<i16>::map_file(output.path(), ew, eh)
+                    .unwrap();
+            assert_eq!(result.pixels(), expected.pixels(), "{:?}", o);
+        }
+    }
+
+    #[test]
+    fn generated_patterns_are_processed() {
+        let output = NamedTempFile::new().unwrap();
+        let mut opts = make_options(&output, &output, PixelFormat::I16);
+        opts.action = Action::Generate(Pattern::Gradient(Axis::X));
+        opts.crop = Some(Region {
+                             origin: (1isize * PX, 0isize * PX),
+                             size: (4isize * PX, 2isize * PX),
+                         });
+        opts.orient = Some(Orientation::FlipHorizontal);
+        opts.resize = Some((4isize * PX, 1isize * PX));
+        opts.interpolation = Interpolation::NearestNeighbour;
+        dispatch(&opts).unwrap();
+
+        // columns 1..4 of the gradient, mirrored and squashed to one row
+        let result = MemoryMappedImage::<i16>::map_file(output.path(),
+                                                        4isize * PX,
+                                                        1isize * PX)
+            .unwrap();
+        assert_eq!(result.pixels(), &[4i16, 3, 2, 1]);
+    }
+
+    #[test]
+    fn mismatched_format_is_an_error() {
+        // an i16 file is only half the size expected of an i32 image
+        let src = OwnedImage::<i16>::new(6isize * PX, 4isize * PX);
+        let input = NamedTempFile::new().unwrap();
+        let output = NamedTempFile::new().unwrap();
+        image::write_file(&src, input.path()).unwrap();
+
+        let opts = make_options(&input, &output, PixelFormat::I32);
+        assert!(dispatch(&opts).is_err());
+    }
+}
+
+#[cfg(test)]
+mod test_compare_output {
+    use super::dispatch;
+    use cli::{Action, Input, Options};
+    use convert::ConvertMode;
+    use distort::Interpolation;
+    use image::{self, OwnedImage, PixelFormat};
+    use tempfile::NamedTempFile;
+    use transform::{self, Orientation};
+    use units::PX;
+
+    /// Rotates an image and compares the result against a reference made by
+    /// rotating it with the library directly, with one pixel tweaked by
+    /// `error`
+    fn rotate_and_compare(error: i16, tolerance: f64) -> bool {
+        let src = OwnedImage::<i16>::gradient(6isize * PX,
+                                              4isize * PX,
+                                              ::patterns::Axis::X);
+        let mut expected = transform::rotate90(&src);
+        expected[(1isize * PX, 2isize * PX)] += error;
+
+        let input = NamedTempFile::new().unwrap();
+        let reference = NamedTempFile::new().unwrap();
+        image::write_file(&src, input.path()).unwrap();
+        image::write_file(&expected, reference.path()).unwrap();
+
+        let opts = Options {
+            action: Action::Correct(Input::File(input.path().to_path_buf())),
+            output: None,
+            output_dir: None,
+            width: 6isize * PX,
+            height: 4isize * PX,
+            format: PixelFormat::I16,
+            crop: None,
+            orient: Some(Orientation::Rotate90),
+            resize: None,
+            interpolation: Interpolation::Bilinear,
+            supersample: 1,
+            compare: Some(reference.path().to_path_buf()),
+            tolerance: tolerance,
+            output_format: None,
+            convert_mode: ConvertMode::Saturate,
+            strict: false,
+        };
+        dispatch(&opts).is_ok()
+    }
+
+    #[test]
+    fn matching_images_pass() {
+        assert!(rotate_and_compare(0, 0.0));
+    }
+
+    #[test]
+    fn differences_within_tolerance_pass() {
+        assert!(rotate_and_compare(3, 3.0));
+    }
+
+    #[test]
+    fn differences_outside_tolerance_fail() {
+        assert!(!rotate_and_compare(4, 3.0));
+    }
+}
+
+#[cfg(test)]
+mod test_run_batch {
+    use super::dispatch;
+    use batch::test_util::TempDir;
+    use cli::{Action, Options};
+    use convert::ConvertMode;
+    use distort::Interpolation;
+    use image::{self, MemoryMappedImage, OwnedImage, PixelFormat};
+    use std::fs::File;
+    use std::io::Write;
+    use transform::Orientation;
+    use units::PX;
+
+    #[test]
+    fn corrupt_files_do_not_stop_the_batch() {
+        let (w, h) = (8isize, 4isize);
+        let src = TempDir::new().unwrap();
+        let dst = TempDir::new().unwrap();
+
+        let mut img = OwnedImage::<i16>::new(w * PX, h * PX);
+        img[(0isize * PX, 0isize * PX)] = 42;
+        for name in &["frame_1.raw", "frame_2.raw", "frame_4.raw"] {
+            image::write_file(&img, &src.path().join(name)).unwrap();
+        }
+
+        // a truncated frame
+        File::create(src.path().join("frame_3.raw"))
+            .unwrap()
+            .write_all(&[0u8; 10])
+            .unwrap();
+
+        let opts = Options {
+            action: Action::Batch(vec![src.path().to_path_buf()]),
+            output: None,
+            output_dir: Some(dst.path().join("corrected")),
+            width: w * PX,
+            height: h * PX,
+            format: PixelFormat::I16,
+            crop: None,
+            orient: Some(Orientation::FlipHorizontal),
+            resize: None,
+            interpolation: Interpolation::Bilinear,
+            supersample: 1,
+            compare: None,
+            tolerance: 0.0,
+            output_format: None,
+            convert_mode: ConvertMode::Saturate,
+            strict: false,
+        };
+
+        assert!(dispatch(&opts).is_err());
+
+        let out_dir = dst.path().join("corrected");
+        for name in &["frame_1.raw", "frame_2.raw", "frame_4.raw"] {
+            let path = out_dir.join(name);
+            let result =
+                MemoryMappedImage::<i16>::map_file(&path, w * PX, h * PX)
+                    .unwrap();
+            assert_eq!(result[((w - 1) * PX, 0isize * PX)], 42);
+        }
+        assert!(!out_dir.join("frame_3.raw").exists());
+    }
+}
+
+#[cfg(test)]
+mod test_trailing_data {
+    use super::check_for_trailing_data;
+    use std::io::Cursor;
+
+    #[test]
+    fn exhausted_stream_is_ok() {
+        let mut r = Cursor::new(Vec::<u8>::new());
+        assert!(check_for_trailing_data(&mut r, true).is_ok());
+    }
+
+    #[test]
+    fn trailing_data_is_a_warning() {
+        let mut r = Cursor::new(vec![0u8; 4]);
+        assert!(check_for_trailing_data(&mut r, false).is_ok());
+    }
 
-    let i = image::MemoryMappedImage::<i16>::map_file(f.input.as_path(),
-                                                      f.width,
-                                                      f.height);
+    #[test]
+    fn trailing_data_is_an_error_when_strict() {
+        let mut r = Cursor::new(vec![0u8; 4]);
+        assert!(check_for_trailing_data(&mut r, true).is_err());
+    }
 }
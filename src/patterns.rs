@@ -0,0 +1,241 @@
+use image::{OwnedImage, Pixel};
+use units::{DistPx, PX};
+
+/// Selects the direction along which a gradient pattern increases.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Axis {
+    /// Pixel values increase from left to right
+    X,
+    /// Pixel values increase from top to bottom
+    Y,
+}
+
+/// Builds an image by evaluating a function at every pixel coordinate.
+fn generate<PixelType, F>(width: DistPx,
+                          height: DistPx,
+                          f: F)
+                          -> OwnedImage<PixelType>
+    where PixelType: Pixel,
+          F: Fn(isize, isize) -> PixelType
+{
+    let mut img = OwnedImage::new(width, height);
+    for y in 0..height / PX {
+        for x in 0..width / PX {
+            img[(x * PX, y * PX)] = f(x, y);
+        }
+    }
+    img
+}
+
+impl<PixelType: Pixel> OwnedImage<PixelType> {
+    /// Creates a checkerboard of square cells alternating between `low` and
+    /// `high`, with a `low` cell in the top-left corner. Cells that don't fit
+    /// evenly into the image are truncated at the right and bottom edges.
+    pub fn checkerboard(width: DistPx,
+                        height: DistPx,
+                        cell_size: DistPx,
+                        low: PixelType,
+                        high: PixelType)
+                        -> OwnedImage<PixelType> {
+        let cell = cell_size / PX;
+        assert!(cell > 0, "checkerboard cell size must be positive");
+
+        generate(width, height, |x, y| if ((x / cell) + (y / cell)) % 2 == 0 {
+            low
+        } else {
+            high
+        })
+    }
+
+    /// Creates a linear ramp where each pixel's value is its coordinate along
+    /// the given axis, i.e. zero at the left (or top) edge, increasing by one
+    /// per pixel. Values too large for the pixel type saturate at its limit.
+    pub fn gradient(width: DistPx,
+                    height: DistPx,
+                    axis: Axis)
+                    -> OwnedImage<PixelType> {
+        generate(width, height, |x, y| {
+            let n = match axis {
+                Axis::X => x,
+                Axis::Y => y,
+            };
+            PixelType::from_f64_saturating(n as f64)
+        })
+    }
+
+    /// Creates a grid of 1-pixel-wide lines on a flat background. Lines are
+    /// drawn every `spacing` pixels starting at the top-left corner, and the
+    /// right and bottom borders are always drawn so that the grid is closed on
+    /// all four sides, even if `spacing` doesn't divide the dimensions evenly.
+    ///
+    /// Straight lines should stay straight after a correct barrel distortion
+    /// correction, which makes this pattern useful for eyeballing the results.
+    pub fn grid(width: DistPx,
+                height: DistPx,
+                spacing: DistPx,
+                line_value: PixelType,
+                bg_value: PixelType)
+                -> OwnedImage<PixelType> {
+        let s = spacing / PX;
+        assert!(s > 0, "grid spacing must be positive");
+
+        let (right, bottom) = ((width / PX) - 1, (height / PX) - 1);
+        generate(width, height, |x, y| {
+            let on_line = (x % s == 0) || (y % s == 0) || (x == right) ||
+                          (y == bottom);
+            if on_line { line_value } else { bg_value }
+        })
+    }
+}
+
+#[cfg(test)]
+mod test_checkerboard {
+    use image::{Image, OwnedImage};
+    use units::PX;
+
+    #[test]
+    fn cells_alternate() {
+        let img = OwnedImage::<i16>::checkerboard(64isize * PX,
+                                                  32isize * PX,
+                                                  8isize * PX,
+                                                  10,
+                                                  20);
+
+        assert_eq!(img[(0isize * PX, 0isize * PX)], 10);
+        assert_eq!(img[(7isize * PX, 7isize * PX)], 10);
+        assert_eq!(img[(8isize * PX, 0isize * PX)], 20);
+        assert_eq!(img[(0isize * PX, 8isize * PX)], 20);
+        assert_eq!(img[(8isize * PX, 8isize * PX)], 10);
+        assert_eq!(img[(63isize * PX, 31isize * PX)], 10);
+    }
+
+    #[test]
+    fn pattern_repeats_every_two_cells() {
+        let cell = 5isize;
+        let img = OwnedImage::<i32>::checkerboard(40isize * PX,
+                                                  30isize * PX,
+                                                  cell * PX,
+                                                  0,
+                                                  1);
+        let (w, h) = img.dimensions();
+        for y in 0..(h / PX) - (2 * cell) {
+            for x in 0..(w / PX) - (2 * cell) {
+                let p = img[(x * PX, y * PX)];
+                assert_eq!(p, img[((x + 2 * cell) * PX, y * PX)]);
+                assert_eq!(p, img[(x * PX, (y + 2 * cell) * PX)]);
+                assert!(p != img[((x + cell) * PX, y * PX)]);
+            }
+        }
+    }
+
+    #[test]
+    fn partial_cells_are_truncated() {
+        // 10 / 4 leaves a 2-pixel wide partial cell on the right and bottom
+        let img = OwnedImage::<i16>::checkerboard(10isize * PX,
+                                                  10isize * PX,
+                                                  4isize * PX,
+                                                  0,
+                                                  1);
+        assert_eq!(img[(7isize * PX, 0isize * PX)], 1);
+        assert_eq!(img[(8isize * PX, 0isize * PX)], 0);
+        assert_eq!(img[(9isize * PX, 0isize * PX)], 0);
+        assert_eq!(img[(9isize * PX, 9isize * PX)], 0);
+        assert_eq!(img[(3isize * PX, 9isize * PX)], 0);
+        assert_eq!(img[(4isize * PX, 9isize * PX)], 1);
+    }
+}
+
+#[cfg(test)]
+mod test_gradient {
+    use super::Axis;
+    use image::OwnedImage;
+    use units::PX;
+
+    #[test]
+    fn x_axis() {
+        let img = OwnedImage::<f32>::gradient(16isize * PX, 4isize * PX, Axis::X);
+        for y in 0..4isize {
+            for x in 0..16isize {
+                assert_eq!(img[(x * PX, y * PX)], x as f32);
+            }
+        }
+    }
+
+    #[test]
+    fn y_axis() {
+        let img = OwnedImage::<i16>::gradient(4isize * PX, 16isize * PX, Axis::Y);
+        assert_eq!(img[(0isize * PX, 0isize * PX)], 0);
+        assert_eq!(img[(3isize * PX, 0isize * PX)], 0);
+        assert_eq!(img[(0isize * PX, 7isize * PX)], 7);
+        assert_eq!(img[(3isize * PX, 15isize * PX)], 15);
+    }
+
+    #[test]
+    fn values_saturate() {
+        let img =
+            OwnedImage::<i16>::gradient(40000isize * PX, 1isize * PX, Axis::X);
+        assert_eq!(img[(32767isize * PX, 0isize * PX)], i16::max_value());
+        assert_eq!(img[(39999isize * PX, 0isize * PX)], i16::max_value());
+    }
+}
+
+#[cfg(test)]
+mod test_grid {
+    use image::{Image, OwnedImage};
+    use units::PX;
+
+    #[test]
+    fn lines_repeat_every_spacing() {
+        let s = 8isize;
+        let img = OwnedImage::<i16>::grid(33isize * PX,
+                                          17isize * PX,
+                                          s * PX,
+                                          1,
+                                          0);
+        let (w, h) = img.dimensions();
+        for y in 0..(h / PX) - s - 1 {
+            for x in 0..(w / PX) - s - 1 {
+                let p = img[(x * PX, y * PX)];
+                assert_eq!(p, img[((x + s) * PX, y * PX)]);
+                assert_eq!(p, img[(x * PX, (y + s) * PX)]);
+            }
+        }
+
+        assert_eq!(img[(8isize * PX, 3isize * PX)], 1);
+        assert_eq!(img[(3isize * PX, 16isize * PX)], 1);
+        assert_eq!(img[(3isize * PX, 3isize * PX)], 0);
+        assert_eq!(img[(9isize * PX, 9isize * PX)], 0);
+    }
+
+    #[test]
+    fn borders_are_always_drawn() {
+        // Neither dimension is a multiple of the spacing, so the right and
+        // bottom lines would be missing without special handling
+        let img = OwnedImage::<i16>::grid(10isize * PX,
+                                          7isize * PX,
+                                          4isize * PX,
+                                          1,
+                                          0);
+        for x in 0..10isize {
+            assert_eq!(img[(x * PX, 0isize * PX)], 1);
+            assert_eq!(img[(x * PX, 6isize * PX)], 1);
+        }
+        for y in 0..7isize {
+            assert_eq!(img[(0isize * PX, y * PX)], 1);
+            assert_eq!(img[(9isize * PX, y * PX)], 1);
+        }
+        assert_eq!(img[(8isize * PX, 1isize * PX)], 1);
+        assert_eq!(img[(7isize * PX, 1isize * PX)], 0);
+        assert_eq!(img[(5isize * PX, 5isize * PX)], 0);
+    }
+
+    #[test]
+    fn single_pixel_image_is_all_line() {
+        let img = OwnedImage::<i16>::grid(1isize * PX,
+                                          1isize * PX,
+                                          4isize * PX,
+                                          1,
+                                          0);
+        assert_eq!(img[(0isize * PX, 0isize * PX)], 1);
+    }
+}
@@ -0,0 +1,316 @@
+use image::{Image, MutableImage, OwnedImage, Pixel};
+use units::{DistPx, PX};
+
+/// Copies a rectangular region out of an image. The region is given as the
+/// coordinates of its top-left pixel and its (width, height).
+///
+/// # Panics
+///
+/// Panics if the region is empty, or does not lie entirely within the source
+/// image.
+pub fn crop<PixelType, ImageType>(src: &ImageType,
+                                  origin: (DistPx, DistPx),
+                                  size: (DistPx, DistPx))
+                                  -> OwnedImage<PixelType>
+    where PixelType: Pixel,
+          ImageType: Image<PixelType>
+{
+    let (src_width, src_height) = src.dimensions();
+    let ((x0, y0), (w, h)) = ((origin.0 / PX, origin.1 / PX),
+                              (size.0 / PX, size.1 / PX));
+    assert!(w > 0 && h > 0, "crop region is empty");
+    assert!(x0 >= 0 && y0 >= 0 &&
+            x0 + w <= src_width / PX && y0 + h <= src_height / PX,
+            "crop region lies outside the source image");
+
+    let (x0, y0, w, h) = (x0 as usize, y0 as usize, w as usize, h as usize);
+    let stride = (src_width / PX) as usize;
+
+    let mut dst = OwnedImage::new(size.0, size.1);
+    {
+        let src_pixels = src.pixels();
+        let dst_pixels = dst.pixels_mut();
+        for y in 0..h {
+            let offset = ((y0 + y) * stride) + x0;
+            dst_pixels[y * w..(y + 1) * w]
+                .copy_from_slice(&src_pixels[offset..offset + w]);
+        }
+    }
+    dst
+}
+
+/// Selects one of the lossless orientation changes that can be applied to an
+/// image. Rotations are clockwise.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Orientation {
+    FlipHorizontal,
+    FlipVertical,
+    Rotate90,
+    Rotate180,
+    Rotate270,
+}
+
+/// Applies an orientation change to an image.
+pub fn orient<PixelType, ImageType>(src: &ImageType,
+                                    orientation: Orientation)
+                                    -> OwnedImage<PixelType>
+    where PixelType: Pixel,
+          ImageType: Image<PixelType>
+{
+    match orientation {
+        Orientation::FlipHorizontal => flip_horizontal(src),
+        Orientation::FlipVertical => flip_vertical(src),
+        Orientation::Rotate90 => rotate90(src),
+        Orientation::Rotate180 => rotate180(src),
+        Orientation::Rotate270 => rotate270(src),
+    }
+}
+
+/// Builds a new image of the given size, where each destination pixel is
+/// copied from the source pixel chosen by `f`. The mapping function is given
+/// and returns unitless pixel coordinates.
+fn remap<PixelType, ImageType, F>(src: &ImageType,
+                                  width: DistPx,
+                                  height: DistPx,
+                                  f: F)
+                                  -> OwnedImage<PixelType>
+    where PixelType: Pixel,
+          ImageType: Image<PixelType>,
+          F: Fn(isize, isize) -> (isize, isize)
+{
+    let mut dst = OwnedImage::new(width, height);
+    for y in 0..height / PX {
+        for x in 0..width / PX {
+            let (sx, sy) = f(x, y);
+            dst[(x * PX, y * PX)] = src[(sx * PX, sy * PX)];
+        }
+    }
+    dst
+}
+
+/// Mirrors an image left-to-right.
+pub fn flip_horizontal<PixelType, ImageType>(src: &ImageType)
+                                             -> OwnedImage<PixelType>
+    where PixelType: Pixel,
+          ImageType: Image<PixelType>
+{
+    let (width, height) = src.dimensions();
+    let right = (width / PX) - 1;
+    remap(src, width, height, |x, y| (right - x, y))
+}
+
+/// Mirrors an image top-to-bottom.
+pub fn flip_vertical<PixelType, ImageType>(src: &ImageType)
+                                           -> OwnedImage<PixelType>
+    where PixelType: Pixel,
+          ImageType: Image<PixelType>
+{
+    let (width, height) = src.dimensions();
+    let bottom = (height / PX) - 1;
+    remap(src, width, height, |x, y| (x, bottom - y))
+}
+
+/// Rotates an image 90 degrees clockwise. The result is as wide as the
+/// source image is high, and vice versa.
+pub fn rotate90<PixelType, ImageType>(src: &ImageType) -> OwnedImage<PixelType>
+    where PixelType: Pixel,
+          ImageType: Image<PixelType>
+{
+    let (width, height) = src.dimensions();
+    let bottom = (height / PX) - 1;
+    remap(src, height, width, |x, y| (y, bottom - x))
+}
+
+/// Rotates an image 180 degrees.
+pub fn rotate180<PixelType, ImageType>(src: &ImageType) -> OwnedImage<PixelType>
+    where PixelType: Pixel,
+          ImageType: Image<PixelType>
+{
+    let (width, height) = src.dimensions();
+    let (right, bottom) = ((width / PX) - 1, (height / PX) - 1);
+    remap(src, width, height, |x, y| (right - x, bottom - y))
+}
+
+/// Rotates an image 270 degrees clockwise (i.e. 90 degrees anticlockwise).
+/// The result is as wide as the source image is high, and vice versa.
+pub fn rotate270<PixelType, ImageType>(src: &ImageType) -> OwnedImage<PixelType>
+    where PixelType: Pixel,
+          ImageType: Image<PixelType>
+{
+    let (width, height) = src.dimensions();
+    let right = (width / PX) - 1;
+    remap(src, height, width, |x, y| (right - y, x))
+}
+
+#[cfg(test)]
+mod test_crop {
+    use super::crop;
+    use image::{Image, OwnedImage};
+    use units::PX;
+
+    fn make_test_image(w: isize, h: isize) -> OwnedImage<i32> {
+        let mut img = OwnedImage::<i32>::new(w * PX, h * PX);
+        for y in 0..h {
+            for x in 0..w {
+                img[(x * PX, y * PX)] = ((1000 * y) + x) as i32;
+            }
+        }
+        img
+    }
+
+    #[test]
+    fn interior_region() {
+        let img = make_test_image(16, 8);
+        let c = crop(&img, (3isize * PX, 2isize * PX), (5isize * PX, 4isize * PX));
+
+        assert_eq!(c.dimensions(), (5isize * PX, 4isize * PX));
+        for y in 0..4isize {
+            for x in 0..5isize {
+                assert_eq!(c[(x * PX, y * PX)],
+                           ((1000 * (y + 2)) + (x + 3)) as i32);
+            }
+        }
+    }
+
+    #[test]
+    fn region_flush_against_bottom_right() {
+        let img = make_test_image(16, 8);
+        let c = crop(&img, (10isize * PX, 5isize * PX), (6isize * PX, 3isize * PX));
+
+        assert_eq!(c.dimensions(), (6isize * PX, 3isize * PX));
+        assert_eq!(c[(0isize * PX, 0isize * PX)], 5010);
+        assert_eq!(c[(5isize * PX, 2isize * PX)], 7015);
+    }
+
+    #[test]
+    fn whole_image() {
+        let img = make_test_image(16, 8);
+        let c = crop(&img, (0isize * PX, 0isize * PX), img.dimensions());
+        assert_eq!(c.pixels(), img.pixels());
+    }
+
+    #[test]
+    #[should_panic]
+    fn region_outside_the_image_panics() {
+        let img = make_test_image(16, 8);
+        crop(&img, (10isize * PX, 5isize * PX), (7isize * PX, 3isize * PX));
+    }
+
+    #[test]
+    #[should_panic]
+    fn zero_area_region_panics() {
+        let img = make_test_image(16, 8);
+        crop(&img, (4isize * PX, 2isize * PX), (0isize * PX, 3isize * PX));
+    }
+}
+
+#[cfg(test)]
+mod test_orientation {
+    use super::*;
+    use image::{Flip, Image, MutableImage, OwnedImage};
+    use units::PX;
+
+    // The test image is 4 x 3, with a distinct value in each corner:
+    //
+    //   1 . . 2
+    //   . . . .
+    //   3 . . 4
+    fn make_test_image() -> OwnedImage<i16> {
+        let mut img = OwnedImage::<i16>::new(4isize * PX, 3isize * PX);
+        img[(0isize * PX, 0isize * PX)] = 1;
+        img[(3isize * PX, 0isize * PX)] = 2;
+        img[(0isize * PX, 2isize * PX)] = 3;
+        img[(3isize * PX, 2isize * PX)] = 4;
+        img
+    }
+
+    /// Reads the corners of an image as (top-left, top-right, bottom-left,
+    /// bottom-right)
+    fn corners(img: &OwnedImage<i16>) -> (i16, i16, i16, i16) {
+        let (w, h) = img.dimensions();
+        let (r, b) = (w - 1isize * PX, h - 1isize * PX);
+        let z = 0isize * PX;
+        (img[(z, z)], img[(r, z)], img[(z, b)], img[(r, b)])
+    }
+
+    #[test]
+    fn flip_horizontal_corners() {
+        let img = flip_horizontal(&make_test_image());
+        assert_eq!(img.dimensions(), (4isize * PX, 3isize * PX));
+        assert_eq!(corners(&img), (2, 1, 4, 3));
+    }
+
+    #[test]
+    fn flip_vertical_corners() {
+        let img = flip_vertical(&make_test_image());
+        assert_eq!(img.dimensions(), (4isize * PX, 3isize * PX));
+        assert_eq!(corners(&img), (3, 4, 1, 2));
+    }
+
+    #[test]
+    fn rotate90_corners() {
+        //   3 . 1
+        //   . . .
+        //   . . .
+        //   4 . 2
+        let img = rotate90(&make_test_image());
+        assert_eq!(img.dimensions(), (3isize * PX, 4isize * PX));
+        assert_eq!(corners(&img), (3, 1, 4, 2));
+    }
+
+    #[test]
+    fn rotate180_corners() {
+        let img = rotate180(&make_test_image());
+        assert_eq!(img.dimensions(), (4isize * PX, 3isize * PX));
+        assert_eq!(corners(&img), (4, 3, 2, 1));
+    }
+
+    #[test]
+    fn rotate270_corners() {
+        //   2 . 4
+        //   . . .
+        //   . . .
+        //   1 . 3
+        let img = rotate270(&make_test_image());
+        assert_eq!(img.dimensions(), (3isize * PX, 4isize * PX));
+        assert_eq!(corners(&img), (2, 4, 1, 3));
+    }
+
+    #[test]
+    fn four_quarter_turns_is_the_identity() {
+        let mut src = OwnedImage::<i16>::new(7isize * PX, 5isize * PX);
+        for y in 0..5isize {
+            for x in 0..7isize {
+                src[(x * PX, y * PX)] = ((100 * y) + x) as i16;
+            }
+        }
+        let img = rotate90(&rotate90(&rotate90(&rotate90(&src))));
+        assert_eq!(img.dimensions(), src.dimensions());
+        assert_eq!(img.pixels(), src.pixels());
+    }
+
+    #[test]
+    fn rotate90_then_rotate270_is_the_identity() {
+        let src = make_test_image();
+        let img = rotate270(&rotate90(&src));
+        assert_eq!(img.pixels(), src.pixels());
+    }
+
+    #[test]
+    fn in_place_flips_match() {
+        let src = make_test_image();
+
+        let mut img = make_test_image();
+        img.flip(Flip::Horizontal);
+        assert_eq!(img.pixels(), flip_horizontal(&src).pixels());
+
+        let mut img = make_test_image();
+        img.flip(Flip::Vertical);
+        assert_eq!(img.pixels(), flip_vertical(&src).pixels());
+
+        let mut img = make_test_image();
+        img.flip(Flip::Horizontal);
+        img.flip(Flip::Vertical);
+        assert_eq!(img.pixels(), rotate180(&src).pixels());
+    }
+}